@@ -0,0 +1,103 @@
+//! Benchmark: StatementCache cost per operation as the cache grows
+//!
+//! Measures lookups, inserts that evict by entry count, and inserts that
+//! evict by byte limit for caches of increasing size. With O(1) LRU
+//! bookkeeping the time per operation should stay flat across sizes.
+//!
+//! ## Running
+//!
+//! ```bash
+//! cargo bench --bench statement_cache
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use diesel_d1::cache::{StatementCache, StatementCacheConfig};
+
+/// Cache sizes to measure
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// Operations timed per measurement
+const OPERATIONS: usize = 100_000;
+
+fn main() {
+    println!(
+        "{:>10} {:>14} {:>22} {:>21}",
+        "entries", "get (ns/op)", "insert, count (ns/op)", "insert, bytes (ns/op)"
+    );
+
+    for size in SIZES {
+        let keys = sql_keys(size + OPERATIONS);
+        let (cached, fresh) = keys.split_at(size);
+
+        let get = {
+            let cache = filled_cache(count_limited(size), cached);
+            time_per_op(|i| {
+                black_box(cache.get(&cached[i % size]));
+            })
+        };
+
+        let insert_by_count = {
+            let cache = filled_cache(count_limited(size), cached);
+            time_per_op(|i| cache.insert(&fresh[i], 1))
+        };
+
+        let insert_by_bytes = {
+            let cache = filled_cache(byte_limited(cached), cached);
+            time_per_op(|i| cache.insert(&fresh[i], 1))
+        };
+
+        println!(
+            "{:>10} {:>14.1} {:>22.1} {:>21.1}",
+            size, get, insert_by_count, insert_by_bytes
+        );
+    }
+}
+
+/// Distinct statements of similar length
+fn sql_keys(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| format!("SELECT * FROM users WHERE id = ? AND shard = {i:08}"))
+        .collect()
+}
+
+/// A cache that holds `size` entries and evicts by entry count
+fn count_limited(size: usize) -> StatementCacheConfig {
+    StatementCacheConfig::builder()
+        .max_entries(size)
+        .no_byte_limit()
+        .build()
+}
+
+/// A cache that is full once `keys` are inserted and evicts by byte limit
+fn byte_limited(keys: &[String]) -> StatementCacheConfig {
+    let cache = filled_cache(
+        StatementCacheConfig::builder()
+            .max_entries(usize::MAX)
+            .no_byte_limit()
+            .build(),
+        keys,
+    );
+    StatementCacheConfig::builder()
+        .max_entries(usize::MAX)
+        .max_bytes(cache.size_bytes())
+        .build()
+}
+
+fn filled_cache(config: StatementCacheConfig, keys: &[String]) -> StatementCache {
+    let cache = StatementCache::new(config);
+    for key in keys {
+        cache.insert(key, 1);
+    }
+    cache
+}
+
+/// Run `op` for every index below `OPERATIONS` and return nanoseconds per call
+fn time_per_op(mut op: impl FnMut(usize)) -> f64 {
+    let start = Instant::now();
+    for i in 0..OPERATIONS {
+        op(i);
+    }
+    start.elapsed().as_nanos() as f64 / OPERATIONS as f64
+}
@@ -26,9 +26,52 @@
 //! // The batch can now be executed atomically
 //! assert_eq!(batch.len(), 3);
 //! ```
+//!
+//! # Guards
+//!
+//! A batch cannot branch, so a precondition such as "the version column still
+//! equals N" cannot stop the statements after it. A guard compiles the check
+//! into a statement that raises an SQL error when the condition does not
+//! hold. The error aborts and rolls back the whole batch, and the connections
+//! report it as a [`GuardFailed`] error naming the guard.
+//!
+//! ```
+//! use diesel_d1::batch::{BatchBuilder, BatchStatement, GuardExpectation};
+//!
+//! let mut batch = BatchBuilder::new();
+//! batch.add_guard(
+//!     "document_version",
+//!     BatchStatement::new("SELECT version FROM documents WHERE id = ?", vec![7.into()]),
+//!     GuardExpectation::Equals(3.into()),
+//! );
+//! batch.add_statement(
+//!     "UPDATE documents SET body = ?, version = version + 1 WHERE id = ?",
+//!     vec!["new body".into(), 7.into()],
+//! );
+//! assert_eq!(batch.len(), 2);
+//! ```
 
 use std::collections::HashMap;
 
+use diesel::{
+    deserialize::FromSqlRow,
+    query_builder::{AsQuery, QueryFragment, QueryId},
+    result::{DatabaseErrorInformation, DatabaseErrorKind},
+    row::Row,
+    QueryResult,
+};
+
+use crate::{backend::D1Backend, bind_collector::BindValue, query_builder::D1QueryBuilder};
+
+/// Maximum number of bound parameters D1 accepts in a single statement
+pub const MAX_BOUND_PARAMETERS: usize = 100;
+
+/// Prefix of the JSON path used to raise a guard failure
+///
+/// SQLite quotes the offending path in its error message, which lets the
+/// guard name travel back inside the D1 error.
+const GUARD_MARKER: &str = "d1_guard_failed[";
+
 /// A bound value for batch operations
 ///
 /// This type represents a parameter value that can be bound to a prepared statement.
@@ -91,6 +134,18 @@ impl From<Option<i64>> for BoundValue {
     }
 }
 
+impl From<BindValue> for BoundValue {
+    fn from(v: BindValue) -> Self {
+        match v {
+            BindValue::Null => BoundValue::Null,
+            BindValue::Integer(i) => BoundValue::Integer(i),
+            BindValue::Double(d) => BoundValue::Float(d),
+            BindValue::Text(s) => BoundValue::Text(s),
+            BindValue::Binary(b) => BoundValue::Binary(b),
+        }
+    }
+}
+
 impl BoundValue {
     /// Convert to a serde_json Value
     #[cfg(feature = "http")]
@@ -157,6 +212,142 @@ impl BatchStatement {
     }
 }
 
+/// The condition a guard query must satisfy for the batch to continue
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuardExpectation {
+    /// The query returns at least one row
+    Exists,
+    /// The query returns no rows
+    NotExists,
+    /// The first column of the first row equals the value
+    ///
+    /// Compared with SQL `IS`, so [`BoundValue::Null`] matches a `NULL`
+    /// result or an empty result set.
+    Equals(BoundValue),
+}
+
+impl GuardExpectation {
+    /// Build the SQL predicate applied to the guard query
+    fn predicate(&self, query: &str) -> String {
+        match self {
+            GuardExpectation::Exists => format!("EXISTS ({})", query),
+            GuardExpectation::NotExists => format!("NOT EXISTS ({})", query),
+            GuardExpectation::Equals(_) => format!("({}) IS ?", query),
+        }
+    }
+}
+
+/// Compile a guard into a statement that fails when its condition is false
+///
+/// The predicate never evaluates to `NULL`, so the `ELSE` branch runs
+/// exactly when the guard fails. It calls `json_extract` with a malformed
+/// path that carries the guard name, which SQLite rejects with an error.
+fn guard_statement(
+    name: &str,
+    query: BatchStatement,
+    expectation: GuardExpectation,
+) -> BatchStatement {
+    let sql = format!(
+        "SELECT CASE WHEN passed THEN 1 ELSE json_extract('{{}}', ? || passed) END \
+         FROM (SELECT {} AS passed)",
+        expectation.predicate(&query.sql)
+    );
+
+    let mut params = Vec::with_capacity(query.params.len() + 2);
+    params.push(BoundValue::Text(format!("{}{}]", GUARD_MARKER, name)));
+    params.extend(query.params);
+    if let GuardExpectation::Equals(value) = expectation {
+        params.push(value);
+    }
+
+    BatchStatement::new(sql, params)
+}
+
+/// Error reported when a batch guard's condition did not hold
+///
+/// Converted into a [`diesel::result::Error::DatabaseError`] with
+/// [`DatabaseErrorKind::CheckViolation`] whose constraint name is the guard
+/// name. Use [`GuardFailed::from_error`] to recover it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuardFailed {
+    /// Name the guard was registered with
+    pub name: String,
+    message: String,
+}
+
+impl GuardFailed {
+    /// Create the error for the named guard
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            message: format!("batch guard '{}' failed", name),
+            name,
+        }
+    }
+
+    /// Recognise a guard failure in a raw D1 error message
+    pub fn from_message(message: &str) -> Option<Self> {
+        let start = message.find(GUARD_MARKER)? + GUARD_MARKER.len();
+        let end = message[start..].find(']')?;
+        Some(Self::new(&message[start..start + end]))
+    }
+
+    /// Recognise a guard failure in a Diesel error
+    pub fn from_error(error: &diesel::result::Error) -> Option<Self> {
+        match error {
+            diesel::result::Error::DatabaseError(DatabaseErrorKind::CheckViolation, info) => {
+                info.constraint_name().map(Self::new)
+            }
+            diesel::result::Error::DatabaseError(_, info) => Self::from_message(info.message()),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for GuardFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for GuardFailed {}
+
+impl DatabaseErrorInformation for GuardFailed {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn details(&self) -> Option<&str> {
+        None
+    }
+
+    fn hint(&self) -> Option<&str> {
+        None
+    }
+
+    fn table_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn column_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn constraint_name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn statement_position(&self) -> Option<i32> {
+        None
+    }
+}
+
+impl From<GuardFailed> for diesel::result::Error {
+    fn from(error: GuardFailed) -> Self {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::CheckViolation, Box::new(error))
+    }
+}
+
 /// Builder for creating batches of statements with prepared query reuse
 ///
 /// This builder collects statements and tracks SQL string reuse for efficient
@@ -213,11 +404,94 @@ impl BatchBuilder {
             .push(BatchStatement::new(sql_string, params));
     }
 
+    /// Add a typed Diesel query to the batch
+    ///
+    /// The query is rendered to SQL and its bind values are collected, so
+    /// `insert_into(...)`, `update(...)` and `delete(...)` builders can be
+    /// batched without falling back to raw SQL.
+    pub fn push_query<Q>(&mut self, query: Q) -> QueryResult<()>
+    where
+        Q: QueryFragment<D1Backend> + QueryId,
+    {
+        let statement = render_query(query)?;
+        self.add_statement(statement.sql, statement.params);
+        Ok(())
+    }
+
+    /// Add a multi-row insert split into chunks that respect D1's
+    /// [`MAX_BOUND_PARAMETERS`] limit
+    ///
+    /// `build` turns a chunk of records into the insert statement, typically
+    /// `|chunk| insert_into(table).values(chunk)`. Because the chunks share
+    /// one batch, the insert stays all-or-nothing. Returns the number of
+    /// statements added.
+    pub fn push_chunked_insert<'a, R, Q, F>(
+        &mut self,
+        records: &'a [R],
+        build: F,
+    ) -> QueryResult<usize>
+    where
+        Q: QueryFragment<D1Backend> + QueryId,
+        F: Fn(&'a [R]) -> Q,
+    {
+        self.push_chunked_insert_with_limit(records, MAX_BOUND_PARAMETERS, build)
+    }
+
+    /// Add a multi-row insert split into chunks that bind at most
+    /// `max_bound_parameters` parameters each
+    ///
+    /// Like [`push_chunked_insert`](Self::push_chunked_insert), for a limit
+    /// configured through [`D1Limits`](crate::limits::D1Limits).
+    pub fn push_chunked_insert_with_limit<'a, R, Q, F>(
+        &mut self,
+        records: &'a [R],
+        max_bound_parameters: usize,
+        build: F,
+    ) -> QueryResult<usize>
+    where
+        Q: QueryFragment<D1Backend> + QueryId,
+        F: Fn(&'a [R]) -> Q,
+    {
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        // Size the chunks from the parameters a single record binds
+        let per_record = render_query(build(&records[..1]))?.param_count().max(1);
+        let chunk_size = (max_bound_parameters / per_record).max(1);
+
+        let mut statements = Vec::new();
+        for chunk in records.chunks(chunk_size) {
+            render_chunk(chunk, max_bound_parameters, &build, &mut statements)?;
+        }
+
+        let added = statements.len();
+        for statement in statements {
+            self.add_statement(statement.sql, statement.params);
+        }
+        Ok(added)
+    }
+
     /// Add a raw SQL statement (no parameters)
     pub fn add_raw(&mut self, sql: impl Into<String>) {
         self.add_statement(sql, vec![]);
     }
 
+    /// Add a guard that aborts the batch unless `query` meets `expectation`
+    ///
+    /// The guard runs at its position in the batch, so it sees the writes of
+    /// the statements added before it. The name is reported back through
+    /// [`GuardFailed`] and must not contain `]`.
+    pub fn add_guard(
+        &mut self,
+        name: impl Into<String>,
+        query: BatchStatement,
+        expectation: GuardExpectation,
+    ) {
+        let statement = guard_statement(&name.into(), query, expectation);
+        self.add_statement(statement.sql, statement.params);
+    }
+
     /// Get the number of statements in the batch
     pub fn len(&self) -> usize {
         self.statements.len()
@@ -255,6 +529,63 @@ impl BatchBuilder {
     }
 }
 
+/// Render a Diesel query into SQL and bound values
+pub(crate) fn render_query<Q>(query: Q) -> QueryResult<BatchStatement>
+where
+    Q: QueryFragment<D1Backend> + QueryId,
+{
+    let mut query_builder = D1QueryBuilder::default();
+    query.to_sql(&mut query_builder, &D1Backend)?;
+
+    let mut bind_collector = crate::bind_collector::D1BindCollector::default();
+    query.collect_binds(&mut bind_collector, &mut (), &D1Backend)?;
+
+    let params = bind_collector
+        .binds
+        .into_iter()
+        .map(|(bind, _)| bind.into())
+        .collect();
+
+    Ok(BatchStatement::new(query_builder.sql, params))
+}
+
+/// Render one chunk of a chunked insert, halving it while it binds too many
+/// parameters
+///
+/// Records can bind different numbers of parameters, so the size estimated
+/// from the first record is only a starting point.
+fn render_chunk<'a, R, Q, F>(
+    chunk: &'a [R],
+    max_bound_parameters: usize,
+    build: &F,
+    statements: &mut Vec<BatchStatement>,
+) -> QueryResult<()>
+where
+    Q: QueryFragment<D1Backend> + QueryId,
+    F: Fn(&'a [R]) -> Q,
+{
+    let statement = render_query(build(chunk))?;
+    if statement.param_count() <= max_bound_parameters {
+        statements.push(statement);
+        return Ok(());
+    }
+
+    if chunk.len() == 1 {
+        return Err(diesel::result::Error::QueryBuilderError(
+            format!(
+                "a single record binds {} parameters, more than the limit of {}",
+                statement.param_count(),
+                max_bound_parameters
+            )
+            .into(),
+        ));
+    }
+
+    let (head, tail) = chunk.split_at(chunk.len() / 2);
+    render_chunk(head, max_bound_parameters, build, statements)?;
+    render_chunk(tail, max_bound_parameters, build, statements)
+}
+
 /// Statistics about SQL string reuse in a batch
 #[derive(Debug, Clone, Copy, Default)]
 pub struct BatchReuseStats {
@@ -290,6 +621,18 @@ pub struct BatchResult {
     pub success: bool,
 }
 
+impl BatchResult {
+    /// Summarise the per-statement results of an executed batch
+    pub fn from_statement_results(statement_results: Vec<StatementResult>) -> Self {
+        Self {
+            successful_statements: statement_results.iter().filter(|r| r.success).count(),
+            total_rows_affected: statement_results.iter().map(|r| r.rows_affected).sum(),
+            success: statement_results.iter().all(|r| r.success),
+            statement_results,
+        }
+    }
+}
+
 /// Result from a single statement in a batch
 #[derive(Debug, Clone)]
 pub struct StatementResult {
@@ -299,6 +642,8 @@ pub struct StatementResult {
     pub rows_affected: usize,
     /// Error message if failed
     pub error: Option<String>,
+    /// Execution details reported by D1, if any
+    pub meta: Option<StatementMeta>,
 }
 
 impl StatementResult {
@@ -308,6 +653,7 @@ impl StatementResult {
             success: true,
             rows_affected,
             error: None,
+            meta: None,
         }
     }
 
@@ -317,10 +663,103 @@ impl StatementResult {
             success: false,
             rows_affected: 0,
             error: Some(error.into()),
+            meta: None,
         }
     }
+
+    /// Attach the execution details D1 reported for the statement
+    pub fn with_meta(mut self, meta: StatementMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+}
+
+/// Execution details D1 reports for a statement
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StatementMeta {
+    /// Time spent executing the statement, in milliseconds
+    pub duration_ms: Option<f64>,
+    /// Number of rows read
+    pub rows_read: Option<usize>,
+    /// Number of rows written
+    pub rows_written: Option<usize>,
+    /// Row ID of the last inserted row
+    pub last_row_id: Option<i64>,
+}
+
+/// A set of queries that can be loaded together in one batch
+///
+/// Implemented for tuples of up to eight queries. `Output` is the matching
+/// tuple of `Vec`s, one per query, each decoded through the row's
+/// `FromSqlRow` implementation.
+pub trait BatchLoad<Output> {
+    /// Render every query into a batch statement, in order
+    fn into_statements(self) -> QueryResult<Vec<BatchStatement>>;
+
+    /// Decode the result sets returned for the statements, in order
+    fn decode_result_sets<R>(result_sets: Vec<Vec<R>>) -> QueryResult<Output>
+    where
+        R: for<'a> Row<'a, D1Backend>;
 }
 
+/// Decode one result set of a [`BatchLoad`] or a cached load
+pub(crate) fn decode_rows<U, ST, R>(rows: Option<Vec<R>>) -> QueryResult<Vec<U>>
+where
+    U: FromSqlRow<ST, D1Backend>,
+    R: for<'a> Row<'a, D1Backend>,
+{
+    let rows = rows.ok_or_else(|| {
+        diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::Unknown,
+            Box::new(crate::utils::D1Error::new(
+                "Missing result set in batch response",
+            )),
+        )
+    })?;
+
+    rows.iter()
+        .map(|row| U::build_from_row(row).map_err(diesel::result::Error::DeserializationError))
+        .collect()
+}
+
+macro_rules! impl_batch_load {
+    ($($T:ident => $U:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($T, $U,)+> BatchLoad<($(Vec<$U>,)+)> for ($($T,)+)
+        where
+            $(
+                $T: AsQuery,
+                $T::Query: QueryFragment<D1Backend> + QueryId,
+                $U: FromSqlRow<$T::SqlType, D1Backend>,
+            )+
+        {
+            fn into_statements(self) -> QueryResult<Vec<BatchStatement>> {
+                let ($($T,)+) = self;
+                let mut batch = BatchBuilder::new();
+                $(batch.push_query($T.as_query())?;)+
+                Ok(batch.into_statements())
+            }
+
+            fn decode_result_sets<R>(result_sets: Vec<Vec<R>>) -> QueryResult<($(Vec<$U>,)+)>
+            where
+                R: for<'a> Row<'a, D1Backend>,
+            {
+                let mut result_sets = result_sets.into_iter();
+                Ok(($(decode_rows::<$U, $T::SqlType, R>(result_sets.next())?,)+))
+            }
+        }
+    };
+}
+
+impl_batch_load!(A => UA);
+impl_batch_load!(A => UA, B => UB);
+impl_batch_load!(A => UA, B => UB, C => UC);
+impl_batch_load!(A => UA, B => UB, C => UC, D => UD);
+impl_batch_load!(A => UA, B => UB, C => UC, D => UD, E => UE);
+impl_batch_load!(A => UA, B => UB, C => UC, D => UD, E => UE, F => UF);
+impl_batch_load!(A => UA, B => UB, C => UC, D => UD, E => UE, F => UF, G => UG);
+impl_batch_load!(A => UA, B => UB, C => UC, D => UD, E => UE, F => UF, G => UG, H => UH);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -400,6 +839,151 @@ mod tests {
         assert_eq!(batch.statements()[0].param_count(), 0);
     }
 
+    #[test]
+    fn test_bound_value_from_bind_value() {
+        assert_eq!(BoundValue::from(BindValue::Null), BoundValue::Null);
+        assert_eq!(
+            BoundValue::from(BindValue::Integer(7)),
+            BoundValue::Integer(7)
+        );
+        assert_eq!(
+            BoundValue::from(BindValue::Double(1.5)),
+            BoundValue::Float(1.5)
+        );
+        assert_eq!(
+            BoundValue::from(BindValue::Text("a".to_string())),
+            BoundValue::Text("a".to_string())
+        );
+        assert_eq!(
+            BoundValue::from(BindValue::Binary(vec![1, 2])),
+            BoundValue::Binary(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn test_batch_builder_push_query() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .push_query(diesel::insert_into(users::table).values(users::name.eq("Alice")))
+            .unwrap();
+        batch
+            .push_query(
+                diesel::update(users::table.filter(users::id.eq(1))).set(users::name.eq("Bob")),
+            )
+            .unwrap();
+
+        let statements = batch.statements();
+        assert_eq!(
+            statements[0].sql(),
+            "INSERT INTO `users` (`name`) VALUES (?)"
+        );
+        assert_eq!(statements[0].params, vec![BoundValue::Text("Alice".into())]);
+        assert_eq!(
+            statements[1].sql(),
+            "UPDATE `users` SET `name` = ? WHERE (`users`.`id` = ?)"
+        );
+        assert_eq!(
+            statements[1].params,
+            vec![BoundValue::Text("Bob".into()), BoundValue::Integer(1)]
+        );
+    }
+
+    #[test]
+    fn test_push_chunked_insert() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        // Two parameters per record, so 50 records fit in one statement
+        let records: Vec<_> = (0..120)
+            .map(|i| (users::id.eq(i), users::name.eq("user")))
+            .collect();
+
+        let mut batch = BatchBuilder::new();
+        let added = batch
+            .push_chunked_insert(&records, |chunk| {
+                diesel::insert_into(users::table).values(chunk.to_vec())
+            })
+            .unwrap();
+
+        assert_eq!(added, 3);
+        let counts: Vec<usize> = batch.statements().iter().map(|s| s.param_count()).collect();
+        assert_eq!(counts, vec![100, 100, 40]);
+        assert_eq!(batch.statements()[2].params[0], BoundValue::Integer(100));
+    }
+
+    #[test]
+    fn test_push_chunked_insert_with_limit() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        let records: Vec<_> = (0..12)
+            .map(|i| (users::id.eq(i), users::name.eq("user")))
+            .collect();
+
+        let mut batch = BatchBuilder::new();
+        let added = batch
+            .push_chunked_insert_with_limit(&records, 10, |chunk| {
+                diesel::insert_into(users::table).values(chunk.to_vec())
+            })
+            .unwrap();
+
+        assert_eq!(added, 3);
+        let counts: Vec<usize> = batch.statements().iter().map(|s| s.param_count()).collect();
+        assert_eq!(counts, vec![10, 10, 4]);
+
+        // A record that alone binds more than the limit cannot be inserted
+        let error = BatchBuilder::new()
+            .push_chunked_insert_with_limit(&records, 1, |chunk| {
+                diesel::insert_into(users::table).values(chunk.to_vec())
+            })
+            .unwrap_err();
+        assert!(error.to_string().contains("more than the limit of 1"));
+    }
+
+    #[test]
+    fn test_push_chunked_insert_empty() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+            }
+        }
+
+        let records: Vec<i32> = vec![];
+        let mut batch = BatchBuilder::new();
+        let added = batch
+            .push_chunked_insert(&records, |chunk| {
+                diesel::insert_into(users::table)
+                    .values(chunk.iter().map(|id| users::id.eq(*id)).collect::<Vec<_>>())
+            })
+            .unwrap();
+
+        assert_eq!(added, 0);
+        assert!(batch.is_empty());
+    }
+
     #[test]
     fn test_batch_builder_reuse_stats() {
         let mut batch = BatchBuilder::new();
@@ -435,6 +1019,30 @@ mod tests {
         assert!(batch.is_empty());
     }
 
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_batch_load_into_statements() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        let queries = (
+            users::table.filter(users::id.eq(1)),
+            users::table.select(users::name),
+        );
+        let statements =
+            <_ as BatchLoad<(Vec<(i32, String)>, Vec<String>)>>::into_statements(queries).unwrap();
+
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].params, vec![BoundValue::Integer(1)]);
+        assert_eq!(statements[1].sql(), "SELECT `users`.`name` FROM `users`");
+    }
+
     #[test]
     fn test_batch_reuse_stats_percentage() {
         let stats = BatchReuseStats {
@@ -460,6 +1068,19 @@ mod tests {
         assert!(result.error.is_none());
     }
 
+    #[test]
+    fn test_statement_result_with_meta() {
+        let meta = StatementMeta {
+            duration_ms: Some(0.5),
+            rows_read: Some(0),
+            rows_written: Some(1),
+            last_row_id: Some(42),
+        };
+        let result = StatementResult::success(1).with_meta(meta);
+        assert_eq!(result.meta, Some(meta));
+        assert!(StatementResult::success(1).meta.is_none());
+    }
+
     #[test]
     fn test_statement_result_failure() {
         let result = StatementResult::failure("Constraint violation");
@@ -468,6 +1089,106 @@ mod tests {
         assert_eq!(result.error, Some("Constraint violation".to_string()));
     }
 
+    #[test]
+    fn test_add_guard_equals() {
+        let mut batch = BatchBuilder::new();
+        batch.add_guard(
+            "version",
+            BatchStatement::new("SELECT version FROM docs WHERE id = ?", vec![7.into()]),
+            GuardExpectation::Equals(3.into()),
+        );
+
+        let guard = &batch.statements()[0];
+        assert_eq!(
+            guard.sql(),
+            "SELECT CASE WHEN passed THEN 1 ELSE json_extract('{}', ? || passed) END \
+             FROM (SELECT (SELECT version FROM docs WHERE id = ?) IS ? AS passed)"
+        );
+        assert_eq!(
+            guard.params,
+            vec![
+                BoundValue::Text("d1_guard_failed[version]".to_string()),
+                BoundValue::Integer(7),
+                BoundValue::Integer(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_guard_exists() {
+        let mut batch = BatchBuilder::new();
+        batch.add_guard(
+            "owner",
+            BatchStatement::new("SELECT 1 FROM users WHERE id = 1", vec![]),
+            GuardExpectation::Exists,
+        );
+        batch.add_guard(
+            "no_lock",
+            BatchStatement::new("SELECT 1 FROM locks", vec![]),
+            GuardExpectation::NotExists,
+        );
+
+        assert!(batch.statements()[0]
+            .sql()
+            .contains("SELECT EXISTS (SELECT 1 FROM users WHERE id = 1) AS passed"));
+        assert!(batch.statements()[1]
+            .sql()
+            .contains("SELECT NOT EXISTS (SELECT 1 FROM locks) AS passed"));
+        assert_eq!(batch.statements()[1].param_count(), 1);
+    }
+
+    #[test]
+    fn test_guard_failed_from_message() {
+        let guard =
+            GuardFailed::from_message("D1_ERROR: bad JSON path: 'd1_guard_failed[version]0'")
+                .unwrap();
+        assert_eq!(guard.name, "version");
+        assert_eq!(guard.to_string(), "batch guard 'version' failed");
+
+        assert!(GuardFailed::from_message("UNIQUE constraint failed: users.email").is_none());
+    }
+
+    #[test]
+    fn test_guard_failed_from_error() {
+        let error: diesel::result::Error = GuardFailed::new("version").into();
+        assert_eq!(
+            GuardFailed::from_error(&error),
+            Some(GuardFailed::new("version"))
+        );
+
+        let error = diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::Unknown,
+            Box::new(crate::utils::D1Error::new(
+                "JSON path error near 'd1_guard_failed[owner]0'",
+            )),
+        );
+        assert_eq!(
+            GuardFailed::from_error(&error),
+            Some(GuardFailed::new("owner"))
+        );
+
+        assert!(GuardFailed::from_error(&diesel::result::Error::NotFound).is_none());
+    }
+
+    #[test]
+    fn test_batch_result_from_statement_results() {
+        let result = BatchResult::from_statement_results(vec![
+            StatementResult::success(2),
+            StatementResult::success(3),
+        ]);
+        assert!(result.success);
+        assert_eq!(result.successful_statements, 2);
+        assert_eq!(result.total_rows_affected, 5);
+
+        let result = BatchResult::from_statement_results(vec![
+            StatementResult::success(1),
+            StatementResult::failure("no such table: t"),
+        ]);
+        assert!(!result.success);
+        assert_eq!(result.successful_statements, 1);
+        assert_eq!(result.total_rows_affected, 1);
+    }
+
     #[cfg(feature = "http")]
     #[test]
     fn test_bound_value_to_json() {
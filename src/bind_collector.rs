@@ -94,7 +94,7 @@ impl<'bind> BindCollector<'bind, D1Backend> for D1BindCollector {
             BindValue::Null
         };
 
-        let metadata = D1Backend::metadata(metadata_lookup);
+        let metadata = <D1Backend as HasSqlType<T>>::metadata(metadata_lookup);
         self.binds.push((bind, metadata));
         Ok(())
     }
@@ -155,4 +155,23 @@ mod tests {
         let collector = D1BindCollector::default();
         assert!(collector.binds.is_empty());
     }
+
+    #[test]
+    fn test_bind_collector_records_sql_type() {
+        use diesel::sql_types::{Integer, Text};
+
+        let mut collector = D1BindCollector::default();
+        collector
+            .push_bound_value::<Integer, i32>(&42, &mut ())
+            .unwrap();
+        collector
+            .push_bound_value::<Text, str>("hello", &mut ())
+            .unwrap();
+
+        assert!(matches!(
+            collector.binds[0],
+            (BindValue::Integer(42), D1Type::Integer)
+        ));
+        assert!(matches!(&collector.binds[1], (BindValue::Text(s), D1Type::Text) if s == "hello"));
+    }
 }
@@ -25,6 +25,9 @@ use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::RwLock;
 
+#[cfg(any(feature = "wasm", feature = "http"))]
+use crate::cache_store::{CachedRows, MemoryResultStore, ResultCacheStore};
+
 /// Default maximum number of cached statement entries
 pub const DEFAULT_MAX_ENTRIES: usize = 128;
 
@@ -178,6 +181,10 @@ impl CacheEntry {
 /// This cache stores prepared statement metadata by SQL string key.
 /// It is safe for concurrent access through interior mutability with RwLock.
 ///
+/// Entries are kept in a hash map plus a doubly-linked recency list, and
+/// their total size is tracked as a running count, so lookups, inserts and
+/// evictions take constant time however large the cache grows.
+///
 /// # Example
 ///
 /// ```
@@ -202,7 +209,7 @@ impl CacheEntry {
 /// ```
 pub struct StatementCache {
     config: StatementCacheConfig,
-    entries: RwLock<HashMap<String, CacheEntry>>,
+    entries: RwLock<LruEntries<CacheEntry>>,
     access_counter: AtomicU64,
     stats: CacheStats,
 }
@@ -212,7 +219,7 @@ impl StatementCache {
     pub fn new(config: StatementCacheConfig) -> Self {
         Self {
             config,
-            entries: RwLock::new(HashMap::new()),
+            entries: RwLock::new(LruEntries::new()),
             access_counter: AtomicU64::new(0),
             stats: CacheStats::default(),
         }
@@ -240,35 +247,27 @@ impl StatementCache {
 
     /// Look up a cached statement entry
     ///
-    /// Returns the entry if found and updates access tracking.
+    /// Returns the entry if found and marks it as the most recently used.
     pub fn get(&self, sql: &str) -> Option<CacheEntry> {
         if !self.config.enabled {
             return None;
         }
 
-        // First try read-only access
-        let entries = self.entries.read().ok()?;
+        // A hit reorders the recency list, so a single write lock covers
+        // both the lookup and the update
+        let mut entries = self.entries.write().ok()?;
 
-        if let Some(entry) = entries.get(sql) {
-            self.stats.hits.fetch_add(1, Ordering::Relaxed);
-            // Clone the entry before updating (to avoid holding read lock during write)
-            let mut cloned = entry.clone();
-            cloned.access_count = self.access_counter.fetch_add(1, Ordering::Relaxed);
-            cloned.last_access = cloned.access_count;
-            drop(entries);
-
-            // Update the entry with new access time
-            if let Ok(mut write_entries) = self.entries.write() {
-                if let Some(e) = write_entries.get_mut(sql) {
-                    e.access_count = cloned.access_count;
-                    e.last_access = cloned.last_access;
-                }
+        match entries.get_mut(sql) {
+            Some(entry) => {
+                self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                entry.access_count = self.access_counter.fetch_add(1, Ordering::Relaxed);
+                entry.last_access = entry.access_count;
+                Some(entry.clone())
+            }
+            None => {
+                self.stats.misses.fetch_add(1, Ordering::Relaxed);
+                None
             }
-
-            Some(cloned)
-        } else {
-            self.stats.misses.fetch_add(1, Ordering::Relaxed);
-            None
         }
     }
 
@@ -276,13 +275,11 @@ impl StatementCache {
     ///
     /// If the cache is at capacity, the least recently used entry is evicted.
     pub fn insert(&self, sql: &str, param_count: usize) {
-        if !self.config.enabled {
+        if !self.config.enabled || self.config.max_entries == 0 {
             return;
         }
 
         let access = self.access_counter.fetch_add(1, Ordering::Relaxed);
-        let entry = CacheEntry::new(sql.to_string(), param_count, access);
-        let entry_size = entry.size_bytes();
 
         let mut entries = match self.entries.write() {
             Ok(e) => e,
@@ -290,30 +287,18 @@ impl StatementCache {
         };
 
         // Check if already exists
-        if entries.contains_key(sql) {
+        if let Some(e) = entries.get_mut(sql) {
             // Just update access time
-            if let Some(e) = entries.get_mut(sql) {
-                e.access_count = access;
-                e.last_access = access;
-            }
+            e.access_count = access;
+            e.last_access = access;
             return;
         }
 
-        // Evict entries if at capacity
-        while entries.len() >= self.config.max_entries {
-            self.evict_lru(&mut entries);
-        }
-
-        // Evict entries if over byte limit
-        if let Some(max_bytes) = self.config.max_bytes {
-            let mut current_bytes: usize = entries.values().map(|e| e.size_bytes()).sum();
-            while current_bytes + entry_size > max_bytes && !entries.is_empty() {
-                self.evict_lru(&mut entries);
-                current_bytes = entries.values().map(|e| e.size_bytes()).sum();
-            }
-        }
+        let entry = CacheEntry::new(sql.to_string(), param_count, access);
+        let entry_size = entry.size_bytes();
 
-        entries.insert(sql.to_string(), entry);
+        make_room(&mut entries, &self.config, &self.stats, entry_size);
+        entries.push_front(sql.to_string(), entry, entry_size);
         self.stats.insertions.fetch_add(1, Ordering::Relaxed);
     }
 
@@ -325,7 +310,7 @@ impl StatementCache {
 
         self.entries
             .read()
-            .map(|e| e.contains_key(sql))
+            .map(|e| e.contains(sql))
             .unwrap_or(false)
     }
 
@@ -339,29 +324,194 @@ impl StatementCache {
         self.len() == 0
     }
 
+    /// Get the total approximate size of the cached entries in bytes
+    pub fn size_bytes(&self) -> usize {
+        self.entries.read().map(|e| e.total_bytes()).unwrap_or(0)
+    }
+
     /// Clear all cached entries
     pub fn clear(&self) {
         if let Ok(mut entries) = self.entries.write() {
             entries.clear();
         }
     }
+}
 
-    /// Evict the least recently used entry
-    fn evict_lru(&self, entries: &mut HashMap<String, CacheEntry>) {
-        if entries.is_empty() {
-            return;
+/// Evict least recently used entries until one more of `size_bytes` fits
+/// the entry and byte limits of `config`, counting the evictions
+fn make_room<V>(
+    entries: &mut LruEntries<V>,
+    config: &StatementCacheConfig,
+    stats: &CacheStats,
+    size_bytes: usize,
+) {
+    let over_limit = |entries: &LruEntries<V>| {
+        entries.len() >= config.max_entries
+            || config
+                .max_bytes
+                .is_some_and(|max_bytes| entries.total_bytes() + size_bytes > max_bytes)
+    };
+
+    while over_limit(entries) {
+        if entries.pop_back().is_none() {
+            break;
+        }
+        stats.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Index marking the ends of the recency list
+const NIL: usize = usize::MAX;
+
+/// Node of the recency list
+struct LruNode<V> {
+    key: String,
+    value: V,
+    /// Size the value was added with
+    size_bytes: usize,
+    /// Next more recently used node
+    prev: usize,
+    /// Next less recently used node
+    next: usize,
+}
+
+/// Values keyed by string in a hash map plus a doubly-linked recency list
+///
+/// Nodes live in a slab addressed by the map, and freed slots are reused.
+/// The list runs from the most recently used node at `head` to the least
+/// recently used at `tail`.
+pub(crate) struct LruEntries<V> {
+    index: HashMap<String, usize>,
+    nodes: Vec<Option<LruNode<V>>>,
+    free: Vec<usize>,
+    head: usize,
+    tail: usize,
+    /// Sum of the sizes the values were added with
+    total_bytes: usize,
+}
+
+impl<V> LruEntries<V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            index: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            total_bytes: 0,
         }
+    }
 
-        // Find the entry with the lowest last_access
-        let lru_key = entries
-            .iter()
-            .min_by_key(|(_, e)| e.last_access)
-            .map(|(k, _)| k.clone());
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub(crate) fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Get a value and mark it as the most recently used
+    pub(crate) fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        let slot = *self.index.get(key)?;
+        self.unlink(slot);
+        self.link_front(slot);
+        Some(&mut self.node_mut(slot).value)
+    }
+
+    /// Add a value of `size_bytes` as the most recently used
+    ///
+    /// The key must not be present yet.
+    pub(crate) fn push_front(&mut self, key: String, value: V, size_bytes: usize) {
+        self.total_bytes += size_bytes;
+        let node = LruNode {
+            key: key.clone(),
+            value,
+            size_bytes,
+            prev: NIL,
+            next: NIL,
+        };
+
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                slot
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+
+        self.index.insert(key, slot);
+        self.link_front(slot);
+    }
+
+    /// Remove and return the least recently used value
+    pub(crate) fn pop_back(&mut self) -> Option<V> {
+        if self.tail == NIL {
+            return None;
+        }
+        self.take(self.tail)
+    }
+
+    /// Remove and return the value stored under `key`
+    pub(crate) fn remove(&mut self, key: &str) -> Option<V> {
+        let slot = *self.index.get(key)?;
+        self.take(slot)
+    }
+
+    /// Detach a node and free its slot
+    fn take(&mut self, slot: usize) -> Option<V> {
+        self.unlink(slot);
+        let node = self.nodes[slot].take()?;
+        self.free.push(slot);
+        self.index.remove(&node.key);
+        self.total_bytes -= node.size_bytes;
+        Some(node.value)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn node_mut(&mut self, slot: usize) -> &mut LruNode<V> {
+        self.nodes[slot]
+            .as_mut()
+            .expect("LRU slot referenced by the list is occupied")
+    }
+
+    /// Detach a node from the recency list
+    fn unlink(&mut self, slot: usize) {
+        let LruNode { prev, next, .. } = *self.node_mut(slot);
+
+        match prev {
+            NIL => self.head = next,
+            prev => self.node_mut(prev).next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.node_mut(next).prev = prev,
+        }
+    }
+
+    /// Attach a detached node at the most recently used end
+    fn link_front(&mut self, slot: usize) {
+        let old_head = self.head;
+        {
+            let node = self.node_mut(slot);
+            node.prev = NIL;
+            node.next = old_head;
+        }
 
-        if let Some(key) = lru_key {
-            entries.remove(&key);
-            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+        match old_head {
+            NIL => self.tail = slot,
+            old_head => self.node_mut(old_head).prev = slot,
         }
+        self.head = slot;
     }
 }
 
@@ -410,6 +560,534 @@ impl CacheStatsSnapshot {
     }
 }
 
+/// SQL text of static queries keyed by their `QueryId`
+///
+/// Queries whose type fully determines their SQL (`HAS_STATIC_QUERY_ID` and
+/// safe to cache) are rendered once; later executions only collect binds.
+/// Like Diesel's own statement cache it is unbounded, since the number of
+/// static query types is fixed by the program.
+#[cfg(any(feature = "wasm", feature = "http"))]
+#[derive(Debug, Default)]
+pub(crate) struct SqlCache {
+    sql: HashMap<std::any::TypeId, String>,
+}
+
+#[cfg(any(feature = "wasm", feature = "http"))]
+impl SqlCache {
+    /// Get the SQL for `source`, rendering it only if it is not cached
+    pub(crate) fn sql<T>(&mut self, source: &T) -> diesel::QueryResult<String>
+    where
+        T: diesel::query_builder::QueryFragment<crate::D1Backend> + diesel::query_builder::QueryId,
+    {
+        let key = match T::query_id() {
+            Some(key) if source.is_safe_to_cache_prepared(&crate::D1Backend)? => key,
+            _ => return render_sql(source),
+        };
+
+        if let Some(sql) = self.sql.get(&key) {
+            return Ok(sql.clone());
+        }
+
+        let sql = render_sql(source)?;
+        self.sql.insert(key, sql.clone());
+        Ok(sql)
+    }
+
+    /// Get the number of cached SQL strings
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.sql.len()
+    }
+}
+
+/// Render a query into SQL text
+#[cfg(any(feature = "wasm", feature = "http"))]
+fn render_sql<T>(source: &T) -> diesel::QueryResult<String>
+where
+    T: diesel::query_builder::QueryFragment<crate::D1Backend>,
+{
+    let mut query_builder = crate::query_builder::D1QueryBuilder::default();
+    source.to_sql(&mut query_builder, &crate::D1Backend)?;
+    Ok(query_builder.sql)
+}
+
+/// LRU of prepared statement handles for the WASM connection
+///
+/// Sized and counted like a [`StatementCache`], but keeps the handles
+/// themselves, so evicting a statement drops its handle in the same step.
+/// Handles are reused as-is because `bind` returns a new statement instead
+/// of mutating the cached one.
+#[cfg(feature = "wasm")]
+pub(crate) struct PreparedStatementCache {
+    config: StatementCacheConfig,
+    entries: std::cell::RefCell<LruEntries<crate::binding::D1PreparedStatement>>,
+    stats: CacheStats,
+}
+
+#[cfg(feature = "wasm")]
+impl PreparedStatementCache {
+    /// Create an empty cache sized by the given configuration
+    pub(crate) fn new(config: StatementCacheConfig) -> Self {
+        Self {
+            config,
+            entries: std::cell::RefCell::new(LruEntries::new()),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Get the cached handle for `sql`, preparing and caching it on a miss
+    pub(crate) fn get_or_prepare(
+        &self,
+        sql: &str,
+        binding: &crate::binding::D1Database,
+    ) -> Result<crate::binding::D1PreparedStatement, wasm_bindgen::JsValue> {
+        if !self.config.enabled {
+            return binding.prepare(sql);
+        }
+
+        let mut entries = self.entries.borrow_mut();
+        if let Some(handle) = entries.get_mut(sql) {
+            self.stats.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(handle.clone());
+        }
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+
+        let prepared = binding.prepare(sql)?;
+        if self.config.max_entries > 0 {
+            let size_bytes = sql.len() + std::mem::size_of::<crate::binding::D1PreparedStatement>();
+            make_room(&mut entries, &self.config, &self.stats, size_bytes);
+            entries.push_front(sql.to_string(), prepared.clone(), size_bytes);
+            self.stats.insertions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok(prepared)
+    }
+
+    /// Get cache statistics
+    pub(crate) fn stats(&self) -> CacheStatsSnapshot {
+        CacheStatsSnapshot {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            evictions: self.stats.evictions.load(Ordering::Relaxed),
+            insertions: self.stats.insertions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Default maximum number of cached query results
+pub const DEFAULT_MAX_RESULT_ENTRIES: usize = 256;
+
+/// Configuration for the query result cache
+///
+/// The result cache is opt-in: connections only cache the rows of queries
+/// loaded with `load_cached` once a configuration is set. The configuration
+/// sizes the default in-memory store; see [`crate::cache_store`] for other
+/// stores.
+///
+/// # Example
+///
+/// ```
+/// use diesel_d1::cache::ResultCacheConfig;
+///
+/// let config = ResultCacheConfig::builder()
+///     .max_entries(500)
+///     .build();
+/// assert!(config.enabled());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ResultCacheConfig {
+    /// Maximum number of results in the in-memory store
+    max_entries: usize,
+    /// Whether caching is enabled
+    enabled: bool,
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_RESULT_ENTRIES,
+            enabled: true,
+        }
+    }
+}
+
+impl ResultCacheConfig {
+    /// Create a new configuration with defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a disabled cache configuration
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    /// Create a builder for configuring the cache
+    pub fn builder() -> ResultCacheConfigBuilder {
+        ResultCacheConfigBuilder::default()
+    }
+
+    /// Get the maximum number of entries
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Check if caching is enabled
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Builder for ResultCacheConfig
+#[derive(Debug, Default)]
+pub struct ResultCacheConfigBuilder {
+    max_entries: Option<usize>,
+    enabled: Option<bool>,
+}
+
+impl ResultCacheConfigBuilder {
+    /// Set the maximum number of cached results
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = Some(max);
+        self
+    }
+
+    /// Enable or disable caching
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    /// Build the configuration
+    pub fn build(self) -> ResultCacheConfig {
+        let default = ResultCacheConfig::default();
+        ResultCacheConfig {
+            max_entries: self.max_entries.unwrap_or(default.max_entries),
+            enabled: self.enabled.unwrap_or(default.enabled),
+        }
+    }
+}
+
+/// Query results keyed by SQL plus binds, kept in a [`ResultCacheStore`]
+///
+/// The cache remembers the tables read by every result it stored. Write
+/// statements issued through the connection delete the results of the
+/// tables they touch; writes whose tables cannot be determined delete every
+/// result stored through this cache. Results stored by other connections
+/// or isolates, and writes made elsewhere, are only bounded by the TTL.
+///
+/// Caching is best-effort: store failures on reads and writes of cached
+/// results count as misses, only failed invalidations are reported.
+#[cfg(any(feature = "wasm", feature = "http"))]
+pub(crate) struct ResultCache {
+    enabled: bool,
+    store: Box<dyn ResultCacheStore>,
+    /// Tables read by each result stored through this cache, lowercased
+    tables: HashMap<String, Vec<String>>,
+    stats: CacheStats,
+}
+
+#[cfg(any(feature = "wasm", feature = "http"))]
+impl ResultCache {
+    /// Create a cache backed by an in-memory store sized by `config`
+    pub(crate) fn new(config: ResultCacheConfig) -> Self {
+        Self {
+            enabled: config.enabled(),
+            store: Box::new(MemoryResultStore::new(config.max_entries())),
+            tables: HashMap::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Create an enabled cache backed by `store`
+    pub(crate) fn with_store(store: impl ResultCacheStore + 'static) -> Self {
+        Self {
+            enabled: true,
+            store: Box::new(store),
+            tables: HashMap::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Check if results are cached at all
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Look up a cached result
+    pub(crate) async fn get(&mut self, key: &str) -> Option<CachedRows> {
+        if !self.enabled {
+            return None;
+        }
+
+        let rows = match self.store.get(key).await {
+            Ok(Some(bytes)) => CachedRows::decode(&bytes),
+            Ok(None) => {
+                // Expired or evicted, nothing left to invalidate
+                self.tables.remove(key);
+                None
+            }
+            Err(_) => None,
+        };
+
+        let counter = match rows {
+            Some(_) => &self.stats.hits,
+            None => &self.stats.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        rows
+    }
+
+    /// Cache the result of the query `sql` for `ttl`
+    pub(crate) async fn insert(
+        &mut self,
+        key: String,
+        sql: &str,
+        rows: &CachedRows,
+        ttl: std::time::Duration,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.store.put(&key, rows.encode(), ttl).await.is_ok() {
+            self.tables.insert(key, referenced_tables(sql));
+            self.stats.insertions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Delete the results that read from a table written by `sql`
+    ///
+    /// Read-only statements leave the cache untouched.
+    pub(crate) async fn invalidate(&mut self, sql: &str) -> diesel::QueryResult<()> {
+        if self.tables.is_empty() || is_read_only(sql) {
+            return Ok(());
+        }
+
+        let written = referenced_tables(sql);
+        let keys: Vec<String> = self
+            .tables
+            .iter()
+            .filter(|(_, tables)| {
+                written.is_empty() || tables.iter().any(|table| written.contains(table))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in keys {
+            self.store.delete(&key).await.map_err(|e| {
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::Unknown,
+                    Box::new(crate::utils::D1Error::new(format!(
+                        "Failed to invalidate cached result: {e}"
+                    ))),
+                )
+            })?;
+            self.tables.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Get cache statistics
+    pub(crate) fn stats(&self) -> CacheStatsSnapshot {
+        CacheStatsSnapshot {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            evictions: self.stats.evictions.load(Ordering::Relaxed),
+            insertions: self.stats.insertions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Build the result cache key of a rendered statement
+#[cfg(any(feature = "wasm", feature = "http"))]
+pub(crate) fn result_cache_key(statement: &crate::batch::BatchStatement) -> String {
+    format!("{}\0{:?}", statement.sql, statement.params)
+}
+
+/// Keywords that are followed by a table name
+#[cfg(any(feature = "wasm", feature = "http"))]
+const TABLE_KEYWORDS: &[&str] = &["FROM", "JOIN", "INTO", "UPDATE", "TABLE"];
+
+/// Clause keywords that can follow a table name in a `FROM` list, so are
+/// never an alias
+#[cfg(any(feature = "wasm", feature = "http"))]
+const CLAUSE_KEYWORDS: &[&str] = &[
+    "WHERE",
+    "JOIN",
+    "INNER",
+    "LEFT",
+    "RIGHT",
+    "FULL",
+    "CROSS",
+    "NATURAL",
+    "ON",
+    "USING",
+    "GROUP",
+    "ORDER",
+    "LIMIT",
+    "HAVING",
+    "WINDOW",
+    "UNION",
+    "EXCEPT",
+    "INTERSECT",
+    "SET",
+    "VALUES",
+    "DEFAULT",
+    "SELECT",
+    "RETURNING",
+    "INDEXED",
+    "NOT",
+];
+
+/// A token of SQL text, as far as table name extraction needs it
+#[cfg(any(feature = "wasm", feature = "http"))]
+#[derive(Debug, PartialEq)]
+enum SqlToken {
+    /// A bare word: keyword or unquoted identifier
+    Word(String),
+    /// A quoted identifier
+    Quoted(String),
+    /// Any other character; string literals and numbers are skipped
+    Symbol(char),
+}
+
+/// Split SQL into words, quoted identifiers and symbols
+#[cfg(any(feature = "wasm", feature = "http"))]
+fn sql_tokens(sql: &str) -> Vec<SqlToken> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '\'' => {
+                // String literal, `''` escapes a quote
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                }
+            }
+            '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                let mut name = String::new();
+                while let Some(c) = chars.next() {
+                    if c == close && chars.next_if_eq(&close).is_none() {
+                        break;
+                    }
+                    name.push(c);
+                }
+                tokens.push(SqlToken::Quoted(name));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+                {
+                    word.push(c);
+                }
+                tokens.push(SqlToken::Word(word));
+            }
+            c => tokens.push(SqlToken::Symbol(c)),
+        }
+    }
+
+    tokens
+}
+
+/// Extract the lowercased names of the tables a SQL statement reads or
+/// writes
+///
+/// Looks for the names following `FROM`, `JOIN`, `INTO`, `UPDATE` and
+/// `TABLE`, including comma-separated `FROM` lists and schema-qualified
+/// names. This covers the SQL Diesel generates; exotic raw SQL may yield
+/// an incomplete list.
+#[cfg(any(feature = "wasm", feature = "http"))]
+pub(crate) fn referenced_tables(sql: &str) -> Vec<String> {
+    let tokens = sql_tokens(sql);
+    let is_word = |index: usize, words: &[&str]| matches!(tokens.get(index), Some(SqlToken::Word(w)) if words.iter().any(|k| w.eq_ignore_ascii_case(k)));
+
+    let mut tables: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        if !is_word(index, TABLE_KEYWORDS) {
+            index += 1;
+            continue;
+        }
+        let in_from_list = is_word(index, &["FROM"]);
+        index += 1;
+
+        // `CREATE TABLE IF NOT EXISTS name`, `DROP TABLE IF EXISTS name`
+        while is_word(index, &["IF", "NOT", "EXISTS"]) {
+            index += 1;
+        }
+
+        while let Some(SqlToken::Word(w) | SqlToken::Quoted(w)) = tokens.get(index) {
+            let mut name = w.to_lowercase();
+            index += 1;
+
+            // `schema.table`
+            if tokens.get(index) == Some(&SqlToken::Symbol('.')) {
+                if let Some(SqlToken::Word(w) | SqlToken::Quoted(w)) = tokens.get(index + 1) {
+                    name = w.to_lowercase();
+                    index += 2;
+                }
+            }
+
+            if !tables.contains(&name) {
+                tables.push(name);
+            }
+
+            if !in_from_list {
+                break;
+            }
+
+            // Skip an alias before looking for the next table of the list
+            if is_word(index, &["AS"]) {
+                index += 2;
+            } else if matches!(
+                tokens.get(index),
+                Some(SqlToken::Word(_)) | Some(SqlToken::Quoted(_))
+            ) && !is_word(index, CLAUSE_KEYWORDS)
+            {
+                index += 1;
+            }
+
+            if tokens.get(index) != Some(&SqlToken::Symbol(',')) {
+                break;
+            }
+            index += 1;
+        }
+    }
+
+    tables
+}
+
+/// Keywords of statements that change the database
+#[cfg(any(feature = "wasm", feature = "http"))]
+const WRITE_KEYWORDS: &[&str] = &[
+    "INSERT", "UPDATE", "DELETE", "REPLACE", "UPSERT", "CREATE", "DROP", "ALTER", "PRAGMA",
+    "ATTACH", "DETACH", "VACUUM", "REINDEX", "ANALYZE",
+];
+
+/// Check if a SQL statement only reads, so sending it twice is harmless
+///
+/// A statement counts as read-only if it starts with `SELECT`, `WITH`,
+/// `VALUES` or `EXPLAIN` and contains no keyword of a writing statement.
+#[cfg(any(feature = "wasm", feature = "http"))]
+pub(crate) fn is_read_only(sql: &str) -> bool {
+    let tokens = sql_tokens(sql);
+    let is_word = |token: &SqlToken, words: &[&str]| matches!(token, SqlToken::Word(w) if words.iter().any(|k| w.eq_ignore_ascii_case(k)));
+
+    tokens
+        .first()
+        .is_some_and(|first| is_word(first, &["SELECT", "WITH", "VALUES", "EXPLAIN"]))
+        && !tokens.iter().any(|token| is_word(token, WRITE_KEYWORDS))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -562,6 +1240,70 @@ mod tests {
         assert!(entry.size_bytes() > "SELECT * FROM users".len());
     }
 
+    #[test]
+    fn test_statement_cache_byte_limit_eviction() {
+        let entry_size = CacheEntry::new("SELECT 1".to_string(), 0, 0).size_bytes();
+        let config = StatementCacheConfig::builder()
+            .max_entries(100)
+            .max_bytes(entry_size * 2)
+            .build();
+        let cache = StatementCache::new(config);
+
+        cache.insert("SELECT 1", 0);
+        cache.insert("SELECT 2", 0);
+        assert_eq!(cache.size_bytes(), entry_size * 2);
+
+        cache.get("SELECT 1");
+        cache.insert("SELECT 3", 0);
+
+        assert!(cache.contains("SELECT 1"));
+        assert!(!cache.contains("SELECT 2"));
+        assert!(cache.contains("SELECT 3"));
+        assert_eq!(cache.size_bytes(), entry_size * 2);
+        assert_eq!(cache.stats().evictions, 1);
+
+        cache.clear();
+        assert_eq!(cache.size_bytes(), 0);
+    }
+
+    #[test]
+    fn test_statement_cache_evicts_in_recency_order() {
+        let config = StatementCacheConfig::builder()
+            .max_entries(3)
+            .no_byte_limit()
+            .build();
+        let cache = StatementCache::new(config);
+
+        for sql in ["SELECT 1", "SELECT 2", "SELECT 3"] {
+            cache.insert(sql, 0);
+        }
+        // Recency from oldest: 2, 3, 1
+        cache.get("SELECT 1");
+        // Re-inserting refreshes an entry: 3, 1, 2
+        cache.insert("SELECT 2", 0);
+
+        cache.insert("SELECT 4", 0);
+        assert!(!cache.contains("SELECT 3"));
+        cache.insert("SELECT 5", 0);
+        assert!(!cache.contains("SELECT 1"));
+        cache.insert("SELECT 6", 0);
+        assert!(!cache.contains("SELECT 2"));
+
+        assert_eq!(cache.len(), 3);
+        for sql in ["SELECT 4", "SELECT 5", "SELECT 6"] {
+            assert!(cache.get(sql).is_some());
+        }
+    }
+
+    #[test]
+    fn test_statement_cache_zero_capacity() {
+        let config = StatementCacheConfig::builder().max_entries(0).build();
+        let cache = StatementCache::new(config);
+
+        cache.insert("SELECT 1", 0);
+        assert!(cache.is_empty());
+    }
+
     #[test]
     fn test_statement_cache_update_existing() {
         let cache = StatementCache::with_defaults();
@@ -575,4 +1317,246 @@ mod tests {
         let stats = cache.stats();
         assert_eq!(stats.insertions, 1); // Only counted once
     }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_sql_cache_reuses_static_queries() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        let mut cache = SqlCache::default();
+
+        let first = cache.sql(&users::table.filter(users::id.eq(1))).unwrap();
+        let second = cache.sql(&users::table.filter(users::id.eq(2))).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.len(), 1);
+
+        // A different query type gets its own entry
+        cache.sql(&users::table.select(users::name)).unwrap();
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_sql_cache_skips_dynamic_queries() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+            }
+        }
+
+        let mut cache = SqlCache::default();
+
+        // The rendered IN list depends on the number of values
+        let sql = cache
+            .sql(&users::table.filter(users::id.eq_any(vec![1, 2, 3])))
+            .unwrap();
+        assert!(sql.contains("IN (?, ?, ?)"));
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_result_cache_config_builder() {
+        let config = ResultCacheConfig::builder().max_entries(10).build();
+        assert_eq!(config.max_entries(), 10);
+        assert!(config.enabled());
+
+        assert!(!ResultCacheConfig::disabled().enabled());
+        assert_eq!(
+            ResultCacheConfig::default().max_entries(),
+            DEFAULT_MAX_RESULT_ENTRIES
+        );
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_referenced_tables() {
+        assert_eq!(
+            referenced_tables(
+                "SELECT `users`.`id` FROM `users` INNER JOIN `posts` ON `posts`.`user_id` = `users`.`id`"
+            ),
+            vec!["users", "posts"]
+        );
+        assert_eq!(
+            referenced_tables("INSERT INTO `Users` (`name`) VALUES (?)"),
+            vec!["users"]
+        );
+        assert_eq!(
+            referenced_tables("UPDATE main.users SET name = 'from posts' WHERE id = ?"),
+            vec!["users"]
+        );
+        assert_eq!(
+            referenced_tables("DELETE FROM \"users\" WHERE id IN (SELECT user_id FROM [bans])"),
+            vec!["users", "bans"]
+        );
+        assert_eq!(
+            referenced_tables("SELECT * FROM users u, posts AS p WHERE u.id = p.user_id"),
+            vec!["users", "posts"]
+        );
+        assert_eq!(
+            referenced_tables("CREATE TABLE IF NOT EXISTS users (id INTEGER)"),
+            vec!["users"]
+        );
+        assert!(referenced_tables("SELECT 1").is_empty());
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    fn cached_rows(value: i64) -> CachedRows {
+        let mut rows = CachedRows::new(vec!["id".to_string()]);
+        rows.push_row(vec![crate::BoundValue::Integer(value)]);
+        rows
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[tokio::test]
+    async fn test_result_cache_hit_and_miss() {
+        let mut cache = ResultCache::new(ResultCacheConfig::default());
+        let ttl = std::time::Duration::from_secs(60);
+
+        assert_eq!(cache.get("users").await, None);
+        cache
+            .insert(
+                "users".to_string(),
+                "SELECT * FROM `users`",
+                &cached_rows(1),
+                ttl,
+            )
+            .await;
+        assert_eq!(cache.get("users").await, Some(cached_rows(1)));
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.insertions, 1);
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[tokio::test]
+    async fn test_result_cache_invalidates_written_tables() {
+        let store = std::sync::Arc::new(MemoryResultStore::new(10));
+        let mut cache = ResultCache::with_store(std::sync::Arc::clone(&store));
+        let ttl = std::time::Duration::from_secs(60);
+
+        cache
+            .insert(
+                "users".to_string(),
+                "SELECT * FROM `users`",
+                &cached_rows(1),
+                ttl,
+            )
+            .await;
+        cache
+            .insert(
+                "posts".to_string(),
+                "SELECT * FROM `posts` INNER JOIN `users` ON 1",
+                &cached_rows(2),
+                ttl,
+            )
+            .await;
+        cache
+            .insert(
+                "tags".to_string(),
+                "SELECT * FROM `tags`",
+                &cached_rows(3),
+                ttl,
+            )
+            .await;
+
+        cache
+            .invalidate("UPDATE `users` SET `name` = ?")
+            .await
+            .unwrap();
+        assert_eq!(cache.get("users").await, None);
+        assert_eq!(cache.get("posts").await, None);
+        assert_eq!(cache.get("tags").await, Some(cached_rows(3)));
+
+        // A write without a recognisable table clears everything
+        cache.invalidate("VACUUM").await.unwrap();
+        assert!(store.is_empty());
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[tokio::test]
+    async fn test_result_cache_keeps_entries_on_reads() {
+        let store = std::sync::Arc::new(MemoryResultStore::new(10));
+        let mut cache = ResultCache::with_store(std::sync::Arc::clone(&store));
+
+        cache
+            .insert(
+                "users".to_string(),
+                "SELECT * FROM `users`",
+                &cached_rows(1),
+                std::time::Duration::from_secs(60),
+            )
+            .await;
+
+        cache.invalidate("SELECT * FROM `users`").await.unwrap();
+        // No recognisable table, but still only a read
+        cache.invalidate("SELECT 1").await.unwrap();
+        assert_eq!(cache.get("users").await, Some(cached_rows(1)));
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[tokio::test]
+    async fn test_result_cache_ignores_undecodable_values() {
+        let store = std::sync::Arc::new(MemoryResultStore::new(10));
+        let mut cache = ResultCache::with_store(std::sync::Arc::clone(&store));
+
+        store
+            .put(
+                "users",
+                b"not rows".to_vec(),
+                std::time::Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache.get("users").await, None);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[tokio::test]
+    async fn test_result_cache_disabled() {
+        let mut cache = ResultCache::new(ResultCacheConfig::disabled());
+        cache
+            .insert(
+                "a".to_string(),
+                "SELECT 1",
+                &cached_rows(1),
+                std::time::Duration::from_secs(60),
+            )
+            .await;
+
+        assert_eq!(cache.get("a").await, None);
+        assert_eq!(cache.stats().misses, 0);
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_is_read_only() {
+        assert!(is_read_only("SELECT * FROM users WHERE id = ?"));
+        assert!(is_read_only("select name from users"));
+        assert!(is_read_only(
+            "WITH recent AS (SELECT * FROM posts) SELECT * FROM recent"
+        ));
+        // Keywords inside string literals and quoted names don't count
+        assert!(is_read_only("SELECT 'delete me', \"update\" FROM logs"));
+
+        assert!(!is_read_only("INSERT INTO users (name) VALUES (?)"));
+        assert!(!is_read_only("UPDATE users SET name = ?"));
+        assert!(!is_read_only("DELETE FROM users"));
+        assert!(!is_read_only(
+            "WITH old AS (SELECT id FROM users) DELETE FROM users WHERE id IN old"
+        ));
+        assert!(!is_read_only("SELECT 1; DROP TABLE users"));
+        assert!(!is_read_only(""));
+    }
 }
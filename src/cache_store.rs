@@ -0,0 +1,660 @@
+//! Storage backends for the query result cache
+//!
+//! The result cache keeps query results in a [`ResultCacheStore`]. The
+//! default [`MemoryResultStore`] lives inside the isolate or process and is
+//! lost when it is evicted; on Workers, `WorkersCacheStore` keeps results
+//! in the Workers Cache API so they survive isolate eviction. Any other
+//! storage can be plugged in by implementing the trait.
+//!
+//! Stores only see opaque bytes: results are serialized as [`CachedRows`]
+//! in a stable, versioned binary format that both connections rebuild their
+//! rows from.
+//!
+//! # Example
+//!
+//! ```
+//! use diesel_d1::cache_store::CachedRows;
+//! use diesel_d1::BoundValue;
+//!
+//! let mut rows = CachedRows::new(vec!["id".to_string(), "name".to_string()]);
+//! rows.push_row(vec![BoundValue::Integer(1), BoundValue::Text("Alice".into())]);
+//!
+//! let bytes = rows.encode();
+//! assert_eq!(CachedRows::decode(&bytes), Some(rows));
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::batch::BoundValue;
+use crate::cache::LruEntries;
+#[cfg(feature = "wasm")]
+use crate::utils::SendableFuture;
+
+/// Result type of [`ResultCacheStore`] operations
+pub type StoreResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Storage for cached query results
+///
+/// Keys are derived from the SQL and binds of a query; values are encoded
+/// [`CachedRows`]. A store is expected to stop returning a value once its
+/// TTL has passed.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use async_trait::async_trait;
+/// use diesel_d1::cache_store::{ResultCacheStore, StoreResult};
+///
+/// /// A store that never keeps anything
+/// struct NullStore;
+///
+/// #[async_trait]
+/// impl ResultCacheStore for NullStore {
+///     async fn get(&self, _key: &str) -> StoreResult<Option<Vec<u8>>> {
+///         Ok(None)
+///     }
+///
+///     async fn put(&self, _key: &str, _value: Vec<u8>, _ttl: Duration) -> StoreResult<()> {
+///         Ok(())
+///     }
+///
+///     async fn delete(&self, _key: &str) -> StoreResult<()> {
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait ResultCacheStore: Send + Sync {
+    /// Get the value stored under `key`, unless it has expired
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>>;
+
+    /// Store `value` under `key` for `ttl`
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> StoreResult<()>;
+
+    /// Remove the value stored under `key`
+    async fn delete(&self, key: &str) -> StoreResult<()>;
+}
+
+#[async_trait]
+impl<S: ResultCacheStore + ?Sized> ResultCacheStore for Arc<S> {
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        (**self).get(key).await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> StoreResult<()> {
+        (**self).put(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> StoreResult<()> {
+        (**self).delete(key).await
+    }
+}
+
+/// A stored value with its expiry
+struct MemoryEntry {
+    value: Vec<u8>,
+    /// Expiry in milliseconds since the Unix epoch
+    expires_at: u64,
+}
+
+/// In-memory LRU store with per-entry expiry
+///
+/// Lost on isolate eviction (WASM) or process restart. Wrap it in an
+/// [`Arc`] to share one store between several connections.
+///
+/// # Example
+///
+/// ```
+/// use diesel_d1::cache_store::MemoryResultStore;
+///
+/// let store = MemoryResultStore::new(100);
+/// assert!(store.is_empty());
+/// ```
+pub struct MemoryResultStore {
+    max_entries: usize,
+    entries: Mutex<LruEntries<MemoryEntry>>,
+}
+
+impl MemoryResultStore {
+    /// Create an empty store holding at most `max_entries` values
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            entries: Mutex::new(LruEntries::new()),
+        }
+    }
+
+    /// Get the number of stored values, including expired ones not yet
+    /// dropped
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|e| e.len()).unwrap_or(0)
+    }
+
+    /// Check if the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl ResultCacheStore for MemoryResultStore {
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+
+        let Some(entry) = entries.get_mut(key) else {
+            return Ok(None);
+        };
+        if entry.expires_at <= now_millis() {
+            entries.remove(key);
+            return Ok(None);
+        }
+        Ok(Some(entry.value.clone()))
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> StoreResult<()> {
+        if self.max_entries == 0 {
+            return Ok(());
+        }
+
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+
+        // Expired values are dropped when read; making room only evicts the
+        // least recently used ones
+        entries.remove(key);
+        while entries.len() >= self.max_entries {
+            if entries.pop_back().is_none() {
+                break;
+            }
+        }
+
+        let size_bytes = value.len();
+        let entry = MemoryEntry {
+            value,
+            expires_at: now_millis().saturating_add(ttl.as_millis() as u64),
+        };
+        entries.push_front(key.to_string(), entry, size_bytes);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> StoreResult<()> {
+        self.entries.lock().map_err(|e| e.to_string())?.remove(key);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for MemoryResultStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryResultStore")
+            .field("max_entries", &self.max_entries)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Result store backed by the Workers Cache API
+///
+/// Values are kept in the data center's cache and survive isolate
+/// eviction, but are not shared between data centers. The Cache API is a
+/// no-op on `workers.dev` domains, so results are only cached on routes of
+/// a custom domain. TTLs are rounded up to whole seconds.
+///
+/// ```ignore
+/// let conn = D1Connection::new(env, "DB").with_result_cache_store(WorkersCacheStore::new());
+/// ```
+#[cfg(feature = "wasm")]
+pub struct WorkersCacheStore {
+    cache: worker::Cache,
+    base_url: String,
+}
+
+// SAFETY: this is safe under WASM and workers because there's no threads and therefore no race conditions (at least memory ones)
+#[cfg(feature = "wasm")]
+unsafe impl Send for WorkersCacheStore {}
+#[cfg(feature = "wasm")]
+unsafe impl Sync for WorkersCacheStore {}
+
+#[cfg(feature = "wasm")]
+impl WorkersCacheStore {
+    /// Base URL of the synthetic cache keys
+    pub const DEFAULT_BASE_URL: &'static str = "https://diesel-d1.cache";
+
+    /// Create a store backed by the default cache
+    pub fn new() -> Self {
+        Self::with_cache(worker::Cache::default())
+    }
+
+    /// Create a store backed by the given cache, e.g. one opened by name
+    pub fn with_cache(cache: worker::Cache) -> Self {
+        Self {
+            cache,
+            base_url: Self::DEFAULT_BASE_URL.to_string(),
+        }
+    }
+
+    /// Set the base URL used to build cache keys
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Build the cache URL of a key
+    fn url(&self, key: &str) -> String {
+        format!("{}/{:016x}", self.base_url, fnv1a(key.as_bytes()))
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl Default for WorkersCacheStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl WorkersCacheStore {
+    async fn fetch(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        let response = self
+            .cache
+            .get(self.url(key), false)
+            .await
+            .map_err(|e| e.to_string())?;
+        let Some(mut response) = response else {
+            return Ok(None);
+        };
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+
+        // The URL is a hash of the key, so the full key is stored in the
+        // body to rule out collisions
+        Ok(split_keyed_value(&body)
+            .filter(|(stored_key, _)| *stored_key == key.as_bytes())
+            .map(|(_, value)| value.to_vec()))
+    }
+
+    async fn store(&self, key: &str, value: Vec<u8>, ttl: Duration) -> StoreResult<()> {
+        let mut response =
+            worker::Response::from_bytes(keyed_value(key, &value)).map_err(|e| e.to_string())?;
+        let max_age = ttl.as_millis().div_ceil(1000);
+        response
+            .headers_mut()
+            .set("Cache-Control", &format!("max-age={max_age}"))
+            .map_err(|e| e.to_string())?;
+
+        self.cache
+            .put(self.url(key), response)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> StoreResult<()> {
+        self.cache
+            .delete(self.url(key), false)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[async_trait]
+impl ResultCacheStore for WorkersCacheStore {
+    async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        SendableFuture(self.fetch(key)).await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>, ttl: Duration) -> StoreResult<()> {
+        SendableFuture(self.store(key, value, ttl)).await
+    }
+
+    async fn delete(&self, key: &str) -> StoreResult<()> {
+        SendableFuture(self.remove(key)).await
+    }
+}
+
+/// Prefix a value with its key
+#[cfg(feature = "wasm")]
+fn keyed_value(key: &str, value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + key.len() + value.len());
+    bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.extend_from_slice(value);
+    bytes
+}
+
+/// Split a value written by [`keyed_value`] into its key and value
+#[cfg(feature = "wasm")]
+fn split_keyed_value(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut reader = Reader { bytes };
+    let key_len = reader.u32()? as usize;
+    let key = reader.take(key_len)?;
+    Some((key, reader.bytes))
+}
+
+/// 64-bit FNV-1a hash, stable across builds and platforms
+#[cfg(feature = "wasm")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Milliseconds since the Unix epoch
+pub(crate) fn now_millis() -> u64 {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    {
+        // `SystemTime` is not available on wasm32-unknown-unknown
+        js_sys::Date::now() as u64
+    }
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// Magic bytes at the start of encoded [`CachedRows`]
+const CACHED_ROWS_MAGIC: &[u8; 4] = b"D1RC";
+
+/// Version of the [`CachedRows`] encoding
+const CACHED_ROWS_VERSION: u8 = 1;
+
+/// Value tags of the [`CachedRows`] encoding
+const TAG_NULL: u8 = 0;
+const TAG_INTEGER: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_TEXT: u8 = 3;
+const TAG_BINARY: u8 = 4;
+
+/// The rows of a query result in a backend-independent form
+///
+/// Values use SQLite's storage classes. The encoding is a versioned binary
+/// format: the `D1RC` magic, a version byte, the column names and then
+/// every row as tagged values, with all integers little-endian. Data
+/// written by another version decodes to `None` and is treated as a miss.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CachedRows {
+    /// Column names, in row order
+    pub columns: Vec<String>,
+    /// Row values, one per column
+    pub rows: Vec<Vec<BoundValue>>,
+}
+
+impl CachedRows {
+    /// Create an empty result with the given columns
+    pub fn new(columns: Vec<String>) -> Self {
+        Self {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row of values, one per column
+    pub fn push_row(&mut self, row: Vec<BoundValue>) {
+        self.rows.push(row);
+    }
+
+    /// Serialize the rows
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(CACHED_ROWS_MAGIC);
+        bytes.push(CACHED_ROWS_VERSION);
+
+        write_u32(&mut bytes, self.columns.len());
+        for column in &self.columns {
+            write_bytes(&mut bytes, column.as_bytes());
+        }
+
+        write_u32(&mut bytes, self.rows.len());
+        for row in &self.rows {
+            write_u32(&mut bytes, row.len());
+            for value in row {
+                match value {
+                    BoundValue::Null => bytes.push(TAG_NULL),
+                    BoundValue::Integer(i) => {
+                        bytes.push(TAG_INTEGER);
+                        bytes.extend_from_slice(&i.to_le_bytes());
+                    }
+                    BoundValue::Float(f) => {
+                        bytes.push(TAG_FLOAT);
+                        bytes.extend_from_slice(&f.to_le_bytes());
+                    }
+                    BoundValue::Text(s) => {
+                        bytes.push(TAG_TEXT);
+                        write_bytes(&mut bytes, s.as_bytes());
+                    }
+                    BoundValue::Binary(b) => {
+                        bytes.push(TAG_BINARY);
+                        write_bytes(&mut bytes, b);
+                    }
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// Deserialize rows written by [`encode`](Self::encode)
+    ///
+    /// Returns `None` for malformed data or data of another version.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != CACHED_ROWS_MAGIC || reader.u8()? != CACHED_ROWS_VERSION {
+            return None;
+        }
+
+        let column_count = reader.u32()?;
+        let columns = (0..column_count)
+            .map(|_| reader.string())
+            .collect::<Option<Vec<_>>>()?;
+
+        let row_count = reader.u32()?;
+        let rows = (0..row_count)
+            .map(|_| {
+                let value_count = reader.u32()?;
+                (0..value_count)
+                    .map(|_| reader.value())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        if !reader.bytes.is_empty() {
+            return None;
+        }
+
+        Some(Self { columns, rows })
+    }
+}
+
+/// Write a length as a little-endian `u32`
+fn write_u32(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+/// Write length-prefixed bytes
+fn write_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    write_u32(bytes, value.len());
+    bytes.extend_from_slice(value);
+}
+
+/// Cursor over encoded bytes
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)?.try_into().ok().map(u32::from_le_bytes)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+
+    fn value(&mut self) -> Option<BoundValue> {
+        match self.u8()? {
+            TAG_NULL => Some(BoundValue::Null),
+            TAG_INTEGER => Some(BoundValue::Integer(i64::from_le_bytes(
+                self.take(8)?.try_into().ok()?,
+            ))),
+            TAG_FLOAT => Some(BoundValue::Float(f64::from_le_bytes(
+                self.take(8)?.try_into().ok()?,
+            ))),
+            TAG_TEXT => self.string().map(BoundValue::Text),
+            TAG_BINARY => self.bytes().map(|b| BoundValue::Binary(b.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_rows() -> CachedRows {
+        let mut rows = CachedRows::new(vec!["id".to_string(), "data".to_string()]);
+        rows.push_row(vec![BoundValue::Integer(-7), BoundValue::Null]);
+        rows.push_row(vec![
+            BoundValue::Float(1.5),
+            BoundValue::Text("héllo".to_string()),
+        ]);
+        rows.push_row(vec![
+            BoundValue::Integer(i64::MAX),
+            BoundValue::Binary(vec![0, 1, 255]),
+        ]);
+        rows
+    }
+
+    #[test]
+    fn test_cached_rows_round_trip() {
+        let rows = sample_rows();
+        assert_eq!(CachedRows::decode(&rows.encode()), Some(rows));
+
+        let empty = CachedRows::new(vec![]);
+        assert_eq!(CachedRows::decode(&empty.encode()), Some(empty));
+    }
+
+    #[test]
+    fn test_cached_rows_encoding_is_stable() {
+        let mut rows = CachedRows::new(vec!["a".to_string()]);
+        rows.push_row(vec![BoundValue::Integer(1)]);
+
+        assert_eq!(
+            rows.encode(),
+            [
+                b"D1RC".as_slice(),
+                &[1],
+                &[1, 0, 0, 0, 1, 0, 0, 0, b'a'],
+                &[1, 0, 0, 0, 1, 0, 0, 0, TAG_INTEGER, 1, 0, 0, 0, 0, 0, 0, 0],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_cached_rows_rejects_malformed_data() {
+        let encoded = sample_rows().encode();
+
+        assert_eq!(CachedRows::decode(&encoded[..encoded.len() - 1]), None);
+        assert_eq!(
+            CachedRows::decode(&[encoded.as_slice(), &[0]].concat()),
+            None
+        );
+        assert_eq!(CachedRows::decode(b"nope"), None);
+
+        let mut other_version = encoded.clone();
+        other_version[4] = CACHED_ROWS_VERSION + 1;
+        assert_eq!(CachedRows::decode(&other_version), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_get_put_delete() {
+        let store = MemoryResultStore::new(10);
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(store.get("a").await.unwrap(), None);
+        store.put("a", vec![1, 2], ttl).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), Some(vec![1, 2]));
+
+        store.delete("a").await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), None);
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_expires_values() {
+        let store = MemoryResultStore::new(10);
+        store.put("a", vec![1], Duration::ZERO).await.unwrap();
+
+        assert_eq!(store.get("a").await.unwrap(), None);
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_evicts_lru() {
+        let store = MemoryResultStore::new(2);
+        let ttl = Duration::from_secs(60);
+
+        store.put("a", vec![1], ttl).await.unwrap();
+        store.put("b", vec![2], ttl).await.unwrap();
+        store.get("a").await.unwrap();
+        store.put("c", vec![3], ttl).await.unwrap();
+
+        assert_eq!(store.get("a").await.unwrap(), Some(vec![1]));
+        assert_eq!(store.get("b").await.unwrap(), None);
+        assert_eq!(store.get("c").await.unwrap(), Some(vec![3]));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_replaces_without_evicting() {
+        let store = MemoryResultStore::new(2);
+        let ttl = Duration::from_secs(60);
+
+        store.put("a", vec![1], ttl).await.unwrap();
+        store.put("b", vec![2], ttl).await.unwrap();
+        store.put("a", vec![3], ttl).await.unwrap();
+        assert_eq!(store.len(), 2);
+
+        // Replacing "a" made it the most recently used
+        store.put("c", vec![4], ttl).await.unwrap();
+        assert_eq!(store.get("a").await.unwrap(), Some(vec![3]));
+        assert_eq!(store.get("b").await.unwrap(), None);
+        assert_eq!(store.get("c").await.unwrap(), Some(vec![4]));
+    }
+
+    #[tokio::test]
+    async fn test_shared_store() {
+        let store = Arc::new(MemoryResultStore::new(10));
+        let shared: Box<dyn ResultCacheStore> = Box::new(Arc::clone(&store));
+
+        shared
+            .put("a", vec![1], Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 1);
+    }
+}
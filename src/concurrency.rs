@@ -13,10 +13,19 @@
 //! D1 REST API is rate-limited at the Cloudflare API layer and is generally intended
 //! for "administrative use" rather than high-throughput production workloads.
 
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 
+#[cfg(feature = "http")]
+use crate::rate_limit::RateLimitPolicy;
+use crate::tracing_support::ErrorClass;
+#[cfg(any(feature = "wasm", feature = "http"))]
+use crate::tracing_support::Stopwatch;
+
 /// Default maximum concurrent queries for the concurrency governor
 pub const DEFAULT_MAX_CONCURRENT_QUERIES: usize = 10;
 
@@ -32,6 +41,14 @@ pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 /// to prevent request amplification under load. It does NOT imply
 /// traditional database connection pooling.
 ///
+/// Waiting callers are queued and served in FIFO order: a released permit is
+/// handed directly to the oldest waiter, and `try_acquire` never jumps ahead
+/// of a non-empty queue.
+///
+/// The limit is fixed unless the policy is built with
+/// [`AdaptiveConcurrency`], in which case it moves between a floor and
+/// `max_concurrent_queries` based on the outcomes reported on permits.
+///
 /// # Example
 ///
 /// ```
@@ -45,18 +62,13 @@ pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 /// ```
 #[derive(Debug, Clone)]
 pub struct QueryConcurrencyPolicy {
-    /// Maximum number of concurrent queries allowed
-    max_concurrent_queries: usize,
-    /// Current number of in-flight queries (shared across clones)
-    in_flight: Arc<AtomicUsize>,
+    /// Semaphore shared across clones
+    semaphore: Arc<Semaphore>,
 }
 
 impl Default for QueryConcurrencyPolicy {
     fn default() -> Self {
-        Self {
-            max_concurrent_queries: DEFAULT_MAX_CONCURRENT_QUERIES,
-            in_flight: Arc::new(AtomicUsize::new(0)),
-        }
+        Self::new(DEFAULT_MAX_CONCURRENT_QUERIES)
     }
 }
 
@@ -64,8 +76,7 @@ impl QueryConcurrencyPolicy {
     /// Create a new policy with the specified maximum concurrent queries
     pub fn new(max_concurrent_queries: usize) -> Self {
         Self {
-            max_concurrent_queries,
-            in_flight: Arc::new(AtomicUsize::new(0)),
+            semaphore: Arc::new(Semaphore::new(max_concurrent_queries, None)),
         }
     }
 
@@ -75,26 +86,63 @@ impl QueryConcurrencyPolicy {
     }
 
     /// Get the maximum number of concurrent queries allowed
+    ///
+    /// For an adaptive policy this is the ceiling the limit grows towards;
+    /// see [`current_limit`](Self::current_limit) for the limit in force.
     pub fn max_concurrent_queries(&self) -> usize {
-        self.max_concurrent_queries
+        self.semaphore.max_permits
+    }
+
+    /// Get the limit currently in force
+    pub fn current_limit(&self) -> usize {
+        self.semaphore.lock().limit
+    }
+
+    /// Check if the limit adapts to reported outcomes
+    pub fn is_adaptive(&self) -> bool {
+        self.semaphore.lock().adaptive.is_some()
     }
 
     /// Get the current number of in-flight queries
+    ///
+    /// Permits handed to a waiter that has not resumed yet are counted.
     pub fn current_in_flight(&self) -> usize {
-        self.in_flight.load(Ordering::SeqCst)
+        self.semaphore.lock().in_flight
+    }
+
+    /// Get the number of callers waiting in `acquire`
+    pub fn waiting(&self) -> usize {
+        self.semaphore.lock().waiters.len()
+    }
+
+    /// Get the current limit, load and limit adjustments for metrics
+    pub fn stats(&self) -> ConcurrencyStats {
+        let state = self.semaphore.lock();
+        let (increases, decreases) = state
+            .adaptive
+            .as_ref()
+            .map_or((0, 0), |aimd| (aimd.increases, aimd.decreases));
+        ConcurrencyStats {
+            limit: state.limit,
+            in_flight: state.in_flight,
+            waiting: state.waiters.len(),
+            increases,
+            decreases,
+        }
     }
 
     /// Check if a new query can be started
     ///
-    /// Returns `true` if the current in-flight count is below the maximum.
+    /// Returns `true` if a permit is free and no other caller is waiting.
     pub fn can_acquire(&self) -> bool {
-        self.in_flight.load(Ordering::SeqCst) < self.max_concurrent_queries
+        self.semaphore.lock().has_free_permit()
     }
 
     /// Try to acquire a permit for a new query
     ///
     /// Returns a `ConcurrencyPermit` if successful, or `None` if the
-    /// maximum concurrent queries limit has been reached.
+    /// maximum concurrent queries limit has been reached or other callers
+    /// are already waiting for a permit.
     ///
     /// # Example
     ///
@@ -119,76 +167,474 @@ impl QueryConcurrencyPolicy {
     /// assert!(policy.try_acquire().is_some());
     /// ```
     pub fn try_acquire(&self) -> Option<ConcurrencyPermit> {
-        loop {
-            let current = self.in_flight.load(Ordering::SeqCst);
-            if current >= self.max_concurrent_queries {
-                return None;
-            }
-            if self
-                .in_flight
-                .compare_exchange(current, current + 1, Ordering::SeqCst, Ordering::SeqCst)
-                .is_ok()
-            {
-                return Some(ConcurrencyPermit {
-                    in_flight: Arc::clone(&self.in_flight),
-                });
-            }
-            // CAS failed, retry
+        let mut state = self.semaphore.lock();
+        if !state.has_free_permit() {
+            return None;
         }
+        state.in_flight += 1;
+        Some(ConcurrencyPermit {
+            semaphore: Arc::clone(&self.semaphore),
+            epoch: state.epoch(),
+        })
     }
 
     /// Acquire a permit, waiting asynchronously if necessary
     ///
-    /// This method will yield to allow other tasks to make progress until
-    /// a permit becomes available. In single-threaded WASM environments,
-    /// this uses a cooperative yielding strategy.
-    #[cfg(feature = "wasm")]
+    /// The caller is parked until a permit is handed to it; nothing polls in
+    /// the meantime. Waiters are served in the order they first polled.
+    ///
+    /// The returned future is cancellation safe: dropping it removes the
+    /// caller from the queue, and a permit handed over just before the drop
+    /// is passed on to the next waiter.
     pub async fn acquire(&self) -> ConcurrencyPermit {
-        use std::future::Future;
-        use std::pin::Pin;
-        use std::task::{Context, Poll};
-
-        /// A future that yields control once and then completes
-        struct YieldNow {
-            yielded: bool,
-        }
-
-        impl Future for YieldNow {
-            type Output = ();
-
-            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
-                if self.yielded {
-                    Poll::Ready(())
-                } else {
-                    self.yielded = true;
-                    cx.waker().wake_by_ref();
-                    Poll::Pending
-                }
+        Acquire {
+            semaphore: Arc::clone(&self.semaphore),
+            waiter: None,
+        }
+        .await
+    }
+
+    /// Acquire a permit, giving up after `timeout`
+    ///
+    /// Returns `None` if no permit was handed over before the deadline. The
+    /// caller's place in the queue is released when the timeout fires.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let permit = policy
+    ///     .acquire_timeout(Duration::from_secs(2))
+    ///     .await
+    ///     .ok_or("D1 is saturated")?;
+    /// ```
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    pub async fn acquire_timeout(&self, timeout: Duration) -> Option<ConcurrencyPermit> {
+        use futures_util::future::{select, Either};
+
+        let acquire = std::pin::pin!(self.acquire());
+        let deadline = std::pin::pin!(sleep(timeout));
+        match select(acquire, deadline).await {
+            Either::Left((permit, _)) => Some(permit),
+            Either::Right(_) => None,
+        }
+    }
+
+    /// Acquire a permit, returning it with the time spent waiting for it
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    pub(crate) async fn acquire_timed(&self) -> (ConcurrencyPermit, Duration) {
+        if let Some(permit) = self.try_acquire() {
+            return (permit, Duration::ZERO);
+        }
+        let stopwatch = Stopwatch::start();
+        let permit = self.acquire().await;
+        (permit, stopwatch.elapsed())
+    }
+}
+
+/// Wait for `duration` using the Workers timer
+#[cfg(feature = "wasm")]
+async fn sleep(duration: Duration) {
+    crate::utils::SendableFuture(worker::Delay::from(duration)).await
+}
+
+/// Wait for `duration` using the tokio timer
+#[cfg(all(feature = "http", not(feature = "wasm")))]
+async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+/// Point-in-time view of a [`QueryConcurrencyPolicy`] for metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyStats {
+    /// Limit currently in force
+    pub limit: usize,
+    /// Permits currently held
+    pub in_flight: usize,
+    /// Callers waiting for a permit
+    pub waiting: usize,
+    /// Times an adaptive limit was raised
+    pub increases: u64,
+    /// Times an adaptive limit was cut
+    pub decreases: u64,
+}
+
+/// Default amount an adaptive limit grows per window of successful queries
+pub const DEFAULT_ADAPTIVE_INCREASE: f64 = 1.0;
+
+/// Default factor an adaptive limit is multiplied by on congestion
+pub const DEFAULT_ADAPTIVE_DECREASE_FACTOR: f64 = 0.5;
+
+/// Settings for an adaptive [`QueryConcurrencyPolicy`]
+///
+/// The limit follows additive-increase/multiplicative-decrease. Each
+/// successful query grows it by `increase / limit`, so a full window of
+/// successes raises it by `increase`. A query that fails with
+/// [`ErrorClass::RateLimit`] or [`ErrorClass::Timeout`] multiplies it by
+/// `decrease_factor`; queries that were already in flight when the limit
+/// was cut don't cut it again. The limit stays between `min_limit` and the
+/// policy's `max_concurrent_queries`.
+///
+/// # Example
+///
+/// ```
+/// use diesel_d1::concurrency::{AdaptiveConcurrency, QueryConcurrencyPolicy};
+///
+/// let policy = QueryConcurrencyPolicy::builder()
+///     .max_concurrent_queries(32)
+///     .adaptive(AdaptiveConcurrency::builder().min_limit(2).initial_limit(8).build())
+///     .build();
+///
+/// assert_eq!(policy.current_limit(), 8);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveConcurrency {
+    /// Lowest the limit may be cut to
+    min_limit: usize,
+    /// Limit to start from, defaulting to the maximum
+    initial_limit: Option<usize>,
+    /// Growth per window of successful queries
+    increase: f64,
+    /// Multiplier applied on congestion
+    decrease_factor: f64,
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> Self {
+        Self {
+            min_limit: 1,
+            initial_limit: None,
+            increase: DEFAULT_ADAPTIVE_INCREASE,
+            decrease_factor: DEFAULT_ADAPTIVE_DECREASE_FACTOR,
+        }
+    }
+}
+
+impl AdaptiveConcurrency {
+    /// Create a builder for configuring the adaptive limit
+    pub fn builder() -> AdaptiveConcurrencyBuilder {
+        AdaptiveConcurrencyBuilder::default()
+    }
+
+    /// Get the lowest the limit may be cut to
+    pub fn min_limit(&self) -> usize {
+        self.min_limit
+    }
+
+    /// Get the limit to start from, if not the maximum
+    pub fn initial_limit(&self) -> Option<usize> {
+        self.initial_limit
+    }
+
+    /// Get the growth per window of successful queries
+    pub fn increase(&self) -> f64 {
+        self.increase
+    }
+
+    /// Get the multiplier applied on congestion
+    pub fn decrease_factor(&self) -> f64 {
+        self.decrease_factor
+    }
+}
+
+/// Builder for AdaptiveConcurrency
+#[derive(Debug, Default)]
+pub struct AdaptiveConcurrencyBuilder {
+    min_limit: Option<usize>,
+    initial_limit: Option<usize>,
+    increase: Option<f64>,
+    decrease_factor: Option<f64>,
+}
+
+impl AdaptiveConcurrencyBuilder {
+    /// Set the lowest the limit may be cut to (at least 1)
+    pub fn min_limit(mut self, min: usize) -> Self {
+        self.min_limit = Some(min);
+        self
+    }
+
+    /// Set the limit to start from
+    pub fn initial_limit(mut self, initial: usize) -> Self {
+        self.initial_limit = Some(initial);
+        self
+    }
+
+    /// Set the growth per window of successful queries
+    pub fn increase(mut self, increase: f64) -> Self {
+        self.increase = Some(increase);
+        self
+    }
+
+    /// Set the multiplier applied on congestion, between 0 and 1
+    pub fn decrease_factor(mut self, factor: f64) -> Self {
+        self.decrease_factor = Some(factor);
+        self
+    }
+
+    /// Build the adaptive settings
+    pub fn build(self) -> AdaptiveConcurrency {
+        let default = AdaptiveConcurrency::default();
+        AdaptiveConcurrency {
+            min_limit: self.min_limit.unwrap_or(default.min_limit).max(1),
+            initial_limit: self.initial_limit,
+            increase: self.increase.unwrap_or(default.increase).max(0.0),
+            decrease_factor: self
+                .decrease_factor
+                .unwrap_or(default.decrease_factor)
+                .clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// FIFO semaphore backing `QueryConcurrencyPolicy`
+///
+/// A single mutex guards the counters and the waiter queue. It is only held
+/// for bookkeeping and never across an await, so it works the same on the
+/// single-threaded Workers runtime and on multi-threaded tokio.
+#[derive(Debug)]
+struct Semaphore {
+    /// Configured maximum, which an adaptive limit never exceeds
+    max_permits: usize,
+    state: Mutex<SemaphoreState>,
+}
+
+#[derive(Debug)]
+struct SemaphoreState {
+    /// Limit currently in force
+    limit: usize,
+    /// Permits currently held, including ones handed to a queued waiter
+    in_flight: usize,
+    /// Queued callers, oldest first
+    waiters: VecDeque<Waiter>,
+    /// Waiters that were handed a permit but have not resumed yet
+    granted: Vec<u64>,
+    next_waiter: u64,
+    /// AIMD bookkeeping for an adaptive limit
+    adaptive: Option<Aimd>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    waker: Waker,
+}
+
+/// Additive-increase/multiplicative-decrease state of an adaptive limit
+#[derive(Debug)]
+struct Aimd {
+    config: AdaptiveConcurrency,
+    /// Fractional limit; the limit in force is its floor
+    window: f64,
+    /// Bumped on every cut so permits from before it can be told apart
+    epoch: u64,
+    increases: u64,
+    decreases: u64,
+}
+
+impl Semaphore {
+    fn new(max_permits: usize, adaptive: Option<AdaptiveConcurrency>) -> Self {
+        // An adaptive limit needs room to move between its floor and the maximum
+        let adaptive = adaptive.filter(|_| max_permits > 0).map(|config| {
+            let min_limit = config.min_limit.clamp(1, max_permits);
+            let initial = config
+                .initial_limit
+                .unwrap_or(max_permits)
+                .clamp(min_limit, max_permits);
+            Aimd {
+                config: AdaptiveConcurrency {
+                    min_limit,
+                    ..config
+                },
+                window: initial as f64,
+                epoch: 0,
+                increases: 0,
+                decreases: 0,
             }
+        });
+        let limit = adaptive
+            .as_ref()
+            .map_or(max_permits, |aimd| aimd.window as usize);
+        Self {
+            max_permits,
+            state: Mutex::new(SemaphoreState {
+                limit,
+                in_flight: 0,
+                waiters: VecDeque::new(),
+                granted: Vec::new(),
+                next_waiter: 0,
+                adaptive,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SemaphoreState> {
+        // The state is never left half-updated, so a poisoned lock is still usable
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Return a permit, handing it to the oldest waiter if there is room
+    fn release(&self) {
+        let waker = self.lock().release();
+        if let Some(waker) = waker {
+            waker.wake();
         }
+    }
 
-        loop {
-            if let Some(permit) = self.try_acquire() {
-                return permit;
+    /// Grow an adaptive limit after a successful query
+    fn record_success(&self) {
+        let wakers = self.lock().grow(self.max_permits);
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Cut an adaptive limit after a congested query
+    fn record_congestion(&self, epoch: u64) {
+        self.lock().cut(epoch);
+    }
+}
+
+impl SemaphoreState {
+    fn has_free_permit(&self) -> bool {
+        self.in_flight < self.limit && self.waiters.is_empty()
+    }
+
+    /// Epoch to stamp on a permit handed out now
+    fn epoch(&self) -> u64 {
+        self.adaptive.as_ref().map_or(0, |aimd| aimd.epoch)
+    }
+
+    /// Release one permit and return the waker of the waiter it went to
+    fn release(&mut self) -> Option<Waker> {
+        // After a cut, permits above the new limit are retired instead of
+        // being handed on
+        if self.in_flight <= self.limit {
+            if let Some(waiter) = self.waiters.pop_front() {
+                // The permit changes hands, so `in_flight` stays the same
+                self.granted.push(waiter.id);
+                return Some(waiter.waker);
             }
-            // Yield to allow other tasks to make progress
-            YieldNow { yielded: false }.await;
         }
+        self.in_flight -= 1;
+        None
     }
 
-    /// Acquire a permit, waiting asynchronously if necessary
-    ///
-    /// This method uses a brief sleep to avoid busy-waiting while waiting
-    /// for a permit to become available.
-    #[cfg(all(feature = "http", not(feature = "wasm")))]
-    pub async fn acquire(&self) -> ConcurrencyPermit {
-        use std::time::Duration;
-        loop {
-            if let Some(permit) = self.try_acquire() {
-                return permit;
+    /// Hand out permits to waiters while there is room under the limit
+    fn grant_waiting(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        while self.in_flight < self.limit {
+            let Some(waiter) = self.waiters.pop_front() else {
+                break;
+            };
+            self.in_flight += 1;
+            self.granted.push(waiter.id);
+            wakers.push(waiter.waker);
+        }
+        wakers
+    }
+
+    fn grow(&mut self, max_permits: usize) -> Vec<Waker> {
+        let Some(aimd) = self.adaptive.as_mut() else {
+            return Vec::new();
+        };
+        aimd.window = (aimd.window + aimd.config.increase / aimd.window).min(max_permits as f64);
+        let limit = aimd.window as usize;
+        if limit <= self.limit {
+            return Vec::new();
+        }
+        aimd.increases += 1;
+        self.limit = limit;
+        self.grant_waiting()
+    }
+
+    fn cut(&mut self, epoch: u64) {
+        let Some(aimd) = self.adaptive.as_mut() else {
+            return;
+        };
+        if epoch != aimd.epoch {
+            return;
+        }
+        let min_limit = aimd.config.min_limit as f64;
+        aimd.window = (aimd.window * aimd.config.decrease_factor).max(min_limit);
+        aimd.epoch += 1;
+        aimd.decreases += 1;
+        self.limit = aimd.window as usize;
+    }
+}
+
+/// Future returned by `QueryConcurrencyPolicy::acquire`
+struct Acquire {
+    semaphore: Arc<Semaphore>,
+    /// Queue id once the caller has started waiting
+    waiter: Option<u64>,
+}
+
+impl Acquire {
+    fn permit(&self, epoch: u64) -> ConcurrencyPermit {
+        ConcurrencyPermit {
+            semaphore: Arc::clone(&self.semaphore),
+            epoch,
+        }
+    }
+}
+
+impl Future for Acquire {
+    type Output = ConcurrencyPermit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ConcurrencyPermit> {
+        let mut state = self.semaphore.lock();
+
+        let Some(id) = self.waiter else {
+            if state.has_free_permit() {
+                state.in_flight += 1;
+                let epoch = state.epoch();
+                drop(state);
+                return Poll::Ready(self.permit(epoch));
+            }
+            let id = state.next_waiter;
+            state.next_waiter += 1;
+            state.waiters.push_back(Waiter {
+                id,
+                waker: cx.waker().clone(),
+            });
+            drop(state);
+            self.waiter = Some(id);
+            return Poll::Pending;
+        };
+
+        if let Some(pos) = state.granted.iter().position(|granted| *granted == id) {
+            state.granted.swap_remove(pos);
+            let epoch = state.epoch();
+            drop(state);
+            self.waiter = None;
+            return Poll::Ready(self.permit(epoch));
+        }
+
+        if let Some(waiter) = state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+            if !waiter.waker.will_wake(cx.waker()) {
+                waiter.waker = cx.waker().clone();
             }
-            // Brief sleep to avoid busy-waiting
-            tokio::time::sleep(Duration::from_micros(100)).await;
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        let Some(id) = self.waiter else {
+            return;
+        };
+        let mut state = self.semaphore.lock();
+        let waker = if let Some(pos) = state.granted.iter().position(|granted| *granted == id) {
+            // A permit was handed over but never picked up; pass it on
+            state.granted.swap_remove(pos);
+            state.release()
+        } else {
+            state.waiters.retain(|waiter| waiter.id != id);
+            None
+        };
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
         }
     }
 }
@@ -197,6 +643,7 @@ impl QueryConcurrencyPolicy {
 #[derive(Debug, Default)]
 pub struct QueryConcurrencyPolicyBuilder {
     max_concurrent_queries: Option<usize>,
+    adaptive: Option<AdaptiveConcurrency>,
 }
 
 impl QueryConcurrencyPolicyBuilder {
@@ -206,12 +653,20 @@ impl QueryConcurrencyPolicyBuilder {
         self
     }
 
+    /// Adapt the limit to reported outcomes, up to `max_concurrent_queries`
+    pub fn adaptive(mut self, adaptive: AdaptiveConcurrency) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
     /// Build the policy
     pub fn build(self) -> QueryConcurrencyPolicy {
-        QueryConcurrencyPolicy::new(
-            self.max_concurrent_queries
-                .unwrap_or(DEFAULT_MAX_CONCURRENT_QUERIES),
-        )
+        let max = self
+            .max_concurrent_queries
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_QUERIES);
+        QueryConcurrencyPolicy {
+            semaphore: Arc::new(Semaphore::new(max, self.adaptive)),
+        }
     }
 }
 
@@ -221,12 +676,52 @@ impl QueryConcurrencyPolicyBuilder {
 /// This provides RAII-style cleanup for concurrency permits.
 #[derive(Debug)]
 pub struct ConcurrencyPermit {
-    in_flight: Arc<AtomicUsize>,
+    semaphore: Arc<Semaphore>,
+    /// Adaptive limit epoch the permit was handed out in
+    epoch: u64,
+}
+
+impl ConcurrencyPermit {
+    /// Report that the query this permit was held for succeeded
+    ///
+    /// Grows an adaptive limit; has no effect on a fixed one.
+    pub fn record_success(&self) {
+        self.semaphore.record_success();
+    }
+
+    /// Report that the query this permit was held for failed
+    ///
+    /// [`ErrorClass::RateLimit`] and [`ErrorClass::Timeout`] cut an adaptive
+    /// limit; other failures say nothing about load and are ignored.
+    pub fn record_error(&self, error_class: ErrorClass) {
+        if matches!(error_class, ErrorClass::RateLimit | ErrorClass::Timeout) {
+            self.semaphore.record_congestion(self.epoch);
+        }
+    }
+
+    /// Report the outcome of a query
+    ///
+    /// An error is reported as `error_class` when the backend could tell it,
+    /// from a response status or transport failure; otherwise it is
+    /// classified by its message.
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    pub(crate) fn record_result<T>(
+        &self,
+        result: &diesel::QueryResult<T>,
+        error_class: Option<ErrorClass>,
+    ) {
+        match result {
+            Ok(_) => self.record_success(),
+            Err(error) => self.record_error(
+                error_class.unwrap_or_else(|| ErrorClass::from_error_message(&error.to_string())),
+            ),
+        }
+    }
 }
 
 impl Drop for ConcurrencyPermit {
     fn drop(&mut self) {
-        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.semaphore.release();
     }
 }
 
@@ -265,6 +760,10 @@ pub struct HttpTransportPolicy {
     max_retries: u32,
     /// Base delay for exponential backoff
     retry_base_delay: Duration,
+    /// Whether requests containing writes are retried too (off by default)
+    retry_writes: bool,
+    /// Client-side pacing against the account's API request budget
+    rate_limit: Option<RateLimitPolicy>,
 }
 
 #[cfg(feature = "http")]
@@ -276,6 +775,8 @@ impl Default for HttpTransportPolicy {
             retry_enabled: false, // Explicitly off by default
             max_retries: 3,
             retry_base_delay: Duration::from_millis(100),
+            retry_writes: false,
+            rate_limit: None,
         }
     }
 }
@@ -316,6 +817,16 @@ impl HttpTransportPolicy {
         self.retry_base_delay
     }
 
+    /// Check if requests containing writes are retried too
+    pub fn retry_writes(&self) -> bool {
+        self.retry_writes
+    }
+
+    /// Get the rate limit applied to requests, if any
+    pub fn rate_limit(&self) -> Option<&RateLimitPolicy> {
+        self.rate_limit.as_ref()
+    }
+
     /// Create a configured reqwest Client based on this policy.
     ///
     /// **Note:** The returned client does not enforce request concurrency limits.
@@ -375,6 +886,8 @@ pub struct HttpTransportPolicyBuilder {
     retry_enabled: Option<bool>,
     max_retries: Option<u32>,
     retry_base_delay: Option<Duration>,
+    retry_writes: Option<bool>,
+    rate_limit: Option<RateLimitPolicy>,
 }
 
 #[cfg(feature = "http")]
@@ -413,6 +926,26 @@ impl HttpTransportPolicyBuilder {
         self
     }
 
+    /// Retry requests containing writes as well as reads
+    ///
+    /// A write whose response was lost may already have been applied, so
+    /// retrying it can apply it twice. Only enable this for writes that are
+    /// safe to repeat.
+    pub fn retry_writes(mut self, enabled: bool) -> Self {
+        self.retry_writes = Some(enabled);
+        self
+    }
+
+    /// Pace requests against an API request budget
+    ///
+    /// Clones of the policy share one budget, so connections built from
+    /// this transport policy (or given a clone of the rate limit) draw from
+    /// the same account quota.
+    pub fn rate_limit(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limit = Some(policy);
+        self
+    }
+
     /// Build the HTTP transport policy
     pub fn build(self) -> HttpTransportPolicy {
         let default = HttpTransportPolicy::default();
@@ -424,6 +957,8 @@ impl HttpTransportPolicyBuilder {
             retry_enabled: self.retry_enabled.unwrap_or(default.retry_enabled),
             max_retries: self.max_retries.unwrap_or(default.max_retries),
             retry_base_delay: self.retry_base_delay.unwrap_or(default.retry_base_delay),
+            retry_writes: self.retry_writes.unwrap_or(default.retry_writes),
+            rate_limit: self.rate_limit,
         }
     }
 }
@@ -522,6 +1057,260 @@ mod tests {
         assert_eq!(policy2.current_in_flight(), 2);
     }
 
+    /// Poll a future once with a no-op waker
+    fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+        let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+        Pin::new(future).poll(&mut cx)
+    }
+
+    #[test]
+    fn test_acquire_is_fifo() {
+        let policy = QueryConcurrencyPolicy::new(1);
+        let held = policy.try_acquire().unwrap();
+
+        let mut first = Box::pin(policy.acquire());
+        let mut second = Box::pin(policy.acquire());
+        assert!(poll_once(&mut first).is_pending());
+        assert!(poll_once(&mut second).is_pending());
+        assert_eq!(policy.waiting(), 2);
+
+        drop(held);
+        // The permit went to the first waiter, not to whoever polls next
+        assert!(poll_once(&mut second).is_pending());
+        assert!(policy.try_acquire().is_none());
+        let Poll::Ready(permit) = poll_once(&mut first) else {
+            panic!("first waiter should hold the permit");
+        };
+        assert_eq!(policy.current_in_flight(), 1);
+
+        drop(permit);
+        assert!(poll_once(&mut second).is_ready());
+    }
+
+    #[test]
+    fn test_try_acquire_does_not_barge() {
+        let policy = QueryConcurrencyPolicy::new(1);
+        let held = policy.try_acquire().unwrap();
+
+        let mut waiter = Box::pin(policy.acquire());
+        assert!(poll_once(&mut waiter).is_pending());
+        drop(held);
+
+        assert!(!policy.can_acquire());
+        assert!(policy.try_acquire().is_none());
+        assert!(poll_once(&mut waiter).is_ready());
+    }
+
+    #[test]
+    fn test_cancelled_waiter_leaves_queue() {
+        let policy = QueryConcurrencyPolicy::new(1);
+        let held = policy.try_acquire().unwrap();
+
+        let mut cancelled = Box::pin(policy.acquire());
+        let mut second = Box::pin(policy.acquire());
+        assert!(poll_once(&mut cancelled).is_pending());
+        assert!(poll_once(&mut second).is_pending());
+
+        drop(cancelled);
+        assert_eq!(policy.waiting(), 1);
+        drop(held);
+        assert!(poll_once(&mut second).is_ready());
+    }
+
+    #[test]
+    fn test_cancelled_after_grant_passes_permit_on() {
+        let policy = QueryConcurrencyPolicy::new(1);
+        let held = policy.try_acquire().unwrap();
+
+        let mut cancelled = Box::pin(policy.acquire());
+        let mut second = Box::pin(policy.acquire());
+        assert!(poll_once(&mut cancelled).is_pending());
+        assert!(poll_once(&mut second).is_pending());
+
+        // Hand the permit to the first waiter, then drop it before it resumes
+        drop(held);
+        drop(cancelled);
+        let Poll::Ready(permit) = poll_once(&mut second) else {
+            panic!("permit should pass to the next waiter");
+        };
+        drop(permit);
+        drop(second);
+        assert_eq!(policy.current_in_flight(), 0);
+        assert_eq!(policy.waiting(), 0);
+    }
+
+    fn adaptive_policy(max: usize, initial: usize) -> QueryConcurrencyPolicy {
+        QueryConcurrencyPolicy::builder()
+            .max_concurrent_queries(max)
+            .adaptive(
+                AdaptiveConcurrency::builder()
+                    .min_limit(1)
+                    .initial_limit(initial)
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn test_fixed_policy_ignores_outcomes() {
+        let policy = QueryConcurrencyPolicy::new(4);
+        assert!(!policy.is_adaptive());
+
+        let permit = policy.try_acquire().unwrap();
+        permit.record_error(ErrorClass::RateLimit);
+        permit.record_success();
+        assert_eq!(policy.current_limit(), 4);
+        assert_eq!(policy.stats().decreases, 0);
+    }
+
+    #[test]
+    fn test_adaptive_limit_grows_additively() {
+        let policy = adaptive_policy(8, 2);
+        assert!(policy.is_adaptive());
+        assert_eq!(policy.current_limit(), 2);
+        assert_eq!(policy.max_concurrent_queries(), 8);
+
+        // Each success adds 1/limit: 2.5, 2.9, then 3.24
+        let permit = policy.try_acquire().unwrap();
+        permit.record_success();
+        permit.record_success();
+        assert_eq!(policy.current_limit(), 2);
+        permit.record_success();
+        assert_eq!(policy.current_limit(), 3);
+
+        for _ in 0..100 {
+            permit.record_success();
+        }
+        assert_eq!(policy.current_limit(), 8);
+        assert_eq!(policy.stats().increases, 6);
+    }
+
+    #[test]
+    fn test_adaptive_limit_cut_once_per_epoch() {
+        let policy = adaptive_policy(8, 8);
+        let first = policy.try_acquire().unwrap();
+        let second = policy.try_acquire().unwrap();
+
+        first.record_error(ErrorClass::RateLimit);
+        assert_eq!(policy.current_limit(), 4);
+        // Already in flight when the limit was cut, so it doesn't cut again
+        second.record_error(ErrorClass::Timeout);
+        assert_eq!(policy.current_limit(), 4);
+
+        let third = policy.try_acquire().unwrap();
+        third.record_error(ErrorClass::Timeout);
+        assert_eq!(policy.current_limit(), 2);
+
+        // Errors unrelated to load are ignored
+        let fourth = policy.try_acquire();
+        assert!(fourth.is_none());
+        drop((first, second, third));
+        let fourth = policy.try_acquire().unwrap();
+        fourth.record_error(ErrorClass::SqlError);
+        assert_eq!(policy.current_limit(), 2);
+
+        let stats = policy.stats();
+        assert_eq!(stats.decreases, 2);
+        assert_eq!(stats.limit, 2);
+        assert_eq!(stats.in_flight, 1);
+    }
+
+    #[test]
+    fn test_adaptive_limit_respects_floor() {
+        let policy = QueryConcurrencyPolicy::builder()
+            .max_concurrent_queries(8)
+            .adaptive(AdaptiveConcurrency::builder().min_limit(3).build())
+            .build();
+
+        for _ in 0..4 {
+            policy
+                .try_acquire()
+                .unwrap()
+                .record_error(ErrorClass::RateLimit);
+        }
+        assert_eq!(policy.current_limit(), 3);
+    }
+
+    #[test]
+    fn test_cut_retires_permits_above_limit() {
+        let policy = adaptive_policy(2, 2);
+        let first = policy.try_acquire().unwrap();
+        let second = policy.try_acquire().unwrap();
+        let mut waiter = Box::pin(policy.acquire());
+        assert!(poll_once(&mut waiter).is_pending());
+
+        first.record_error(ErrorClass::RateLimit);
+        assert_eq!(policy.current_limit(), 1);
+
+        // Two permits are out against a limit of one, so the first release
+        // is retired rather than handed to the waiter
+        drop(first);
+        assert!(poll_once(&mut waiter).is_pending());
+        drop(second);
+        assert!(poll_once(&mut waiter).is_ready());
+    }
+
+    #[test]
+    fn test_growth_wakes_waiters() {
+        let policy = adaptive_policy(4, 1);
+        let held = policy.try_acquire().unwrap();
+        let mut waiter = Box::pin(policy.acquire());
+        assert!(poll_once(&mut waiter).is_pending());
+
+        held.record_success();
+        assert_eq!(policy.current_limit(), 2);
+        assert!(poll_once(&mut waiter).is_ready());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_acquire_limits_concurrency_across_threads() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let policy = QueryConcurrencyPolicy::new(3);
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..64)
+            .map(|_| {
+                let policy = policy.clone();
+                let active = Arc::clone(&active);
+                let peak = Arc::clone(&peak);
+                tokio::spawn(async move {
+                    let _permit = policy.acquire().await;
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::task::yield_now().await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(policy.current_in_flight(), 0);
+        assert_eq!(policy.waiting(), 0);
+    }
+
+    #[cfg(all(feature = "http", not(feature = "wasm")))]
+    #[tokio::test]
+    async fn test_acquire_timeout() {
+        let policy = QueryConcurrencyPolicy::new(1);
+        let held = policy.try_acquire().unwrap();
+
+        assert!(policy
+            .acquire_timeout(Duration::from_millis(10))
+            .await
+            .is_none());
+        // The timed-out caller no longer holds a place in the queue
+        assert_eq!(policy.waiting(), 0);
+
+        drop(held);
+        let permit = policy.acquire_timeout(Duration::from_millis(10)).await;
+        assert!(permit.is_some());
+    }
+
     #[cfg(feature = "http")]
     mod http_tests {
         use super::*;
@@ -552,6 +1341,19 @@ mod tests {
             assert!(policy.retry_enabled());
             assert_eq!(policy.max_retries(), 5);
             assert_eq!(policy.retry_base_delay(), Duration::from_millis(200));
+            assert!(!policy.retry_writes());
+            assert!(policy.rate_limit().is_none());
+        }
+
+        #[test]
+        fn test_http_transport_policy_rate_limit_is_shared() {
+            let policy = HttpTransportPolicy::builder()
+                .rate_limit(RateLimitPolicy::new(1, Duration::from_secs(60)))
+                .build();
+            let copy = policy.clone();
+
+            assert!(policy.rate_limit().unwrap().try_acquire().is_ok());
+            assert!(copy.rate_limit().unwrap().try_acquire().is_err());
         }
 
         #[test]
@@ -3,9 +3,13 @@
 //! This module provides the D1HttpConnection type that uses the Cloudflare D1
 //! REST API to interact with D1 databases in non-Workers environments.
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use diesel::{
     connection::{ConnectionSealed, Instrumentation},
+    deserialize::FromSqlRow,
     query_builder::{AsQuery, QueryFragment, QueryId},
     ConnectionResult, QueryResult,
 };
@@ -15,12 +19,33 @@ use futures_util::{
     stream::{self, BoxStream},
     FutureExt, StreamExt,
 };
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    backend::D1Backend, bind_collector::D1BindCollector, http_row::D1Row,
-    query_builder::D1QueryBuilder, transaction_manager::D1TransactionManager, utils::D1Error,
+    backend::D1Backend,
+    batch::{
+        decode_rows, render_query, BatchBuilder, BatchLoad, BatchResult, BatchStatement,
+        BoundValue, StatementMeta, StatementResult,
+    },
+    bind_collector::D1BindCollector,
+    cache::{
+        is_read_only, result_cache_key, CacheStatsSnapshot, ResultCache, ResultCacheConfig,
+        SqlCache,
+    },
+    cache_store::{CachedRows, ResultCacheStore},
+    concurrency::{ConcurrencyPermit, HttpTransportPolicy, QueryConcurrencyPolicy},
+    http_row::D1Row,
+    limits::D1Limits,
+    rate_limit::RateLimitPolicy,
+    tracing_support::{BackendType, ErrorClass, QuerySpan, SpanEmitter, SpanOperation},
+    transaction_manager::{
+        D1HttpTransactionConnection, D1TransactionManager, TransactionReadPolicy,
+    },
+    transport::{
+        transport_error_class, D1Transport, ReqwestTransport, TransportRequest, TransportResponse,
+    },
+    utils::{d1_database_error, D1Error},
 };
 
 /// Configuration for D1 HTTP API connection
@@ -69,10 +94,13 @@ impl D1HttpConfig {
         self
     }
 
-    /// Build the query URL for this database
-    fn query_url(&self) -> String {
+    /// Build the URL of the raw query endpoint for this database
+    ///
+    /// The raw endpoint returns result sets as column names plus rows of
+    /// values, which keeps the column order of the query.
+    fn raw_query_url(&self) -> String {
         format!(
-            "{}/accounts/{}/d1/database/{}/query",
+            "{}/accounts/{}/d1/database/{}/raw",
             self.base_url, self.account_id, self.database_id
         )
     }
@@ -86,6 +114,15 @@ struct D1QueryRequest {
     params: Vec<serde_json::Value>,
 }
 
+/// D1 HTTP API request body for several statements sent in one request
+///
+/// D1 executes every statement of a batch inside a single SQL transaction,
+/// so either all of them are applied or none are.
+#[derive(Serialize, Debug)]
+struct D1BatchQueryRequest {
+    batch: Vec<D1QueryRequest>,
+}
+
 /// D1 HTTP API response
 #[derive(Deserialize, Debug)]
 struct D1ApiResponse {
@@ -105,10 +142,140 @@ struct D1ApiError {
 /// D1 query result
 #[allow(dead_code)]
 #[derive(Deserialize, Debug)]
-struct D1QueryResult {
+pub(crate) struct D1QueryResult {
     success: bool,
-    results: Option<Vec<serde_json::Value>>,
+    results: Option<D1RawResults>,
     meta: Option<D1QueryMeta>,
+    error: Option<String>,
+}
+
+/// Result set of a statement, as returned by the raw query endpoint
+#[derive(Deserialize, Debug, Default)]
+struct D1RawResults {
+    /// Column names, in the order of the query
+    #[serde(default)]
+    columns: Vec<String>,
+    /// Values of each row, in column order
+    #[serde(default)]
+    rows: Vec<Vec<serde_json::Value>>,
+}
+
+impl D1QueryResult {
+    /// Convert the returned records into rows
+    fn into_rows(self) -> Vec<D1Row> {
+        let D1RawResults { columns, rows } = self.results.unwrap_or_default();
+
+        rows.into_iter()
+            .map(|values| D1Row::from_values(values, columns.clone()))
+            .collect()
+    }
+
+    /// Convert the returned records into their cacheable form
+    fn to_cached_rows(&self) -> CachedRows {
+        let Some(results) = &self.results else {
+            return CachedRows::new(vec![]);
+        };
+        let mut rows = CachedRows::new(results.columns.clone());
+
+        for record in &results.rows {
+            rows.push_row(record.iter().map(json_to_bound_value).collect());
+        }
+        rows
+    }
+
+    /// Get the number of rows the statement returned
+    fn row_count(&self) -> usize {
+        self.results
+            .as_ref()
+            .map_or(0, |results| results.rows.len())
+    }
+
+    /// Get the number of rows the statement changed
+    fn changes(&self) -> usize {
+        self.meta.as_ref().and_then(|m| m.changes).unwrap_or(0) as usize
+    }
+
+    /// Convert into the per-statement result of a batch
+    fn into_statement_result(self) -> StatementResult {
+        let result = if self.success {
+            StatementResult::success(self.changes())
+        } else {
+            StatementResult::failure(self.error.unwrap_or_else(|| "Unknown error".to_string()))
+        };
+
+        match self.meta {
+            Some(meta) => result.with_meta(meta.into()),
+            None => result,
+        }
+    }
+}
+
+/// Find the first failed statement of a request and describe its error
+fn failed_statement(results: &[D1QueryResult]) -> Option<String> {
+    let (index, result) = results
+        .iter()
+        .enumerate()
+        .find(|(_, result)| !result.success)?;
+
+    Some(format!(
+        "Statement {} of {} failed: {}",
+        index + 1,
+        results.len(),
+        result.error.as_deref().unwrap_or("Unknown error")
+    ))
+}
+
+/// Outcome of sending one request, beyond its result
+#[derive(Debug, Default)]
+struct RequestAttempts {
+    /// Retries made after the first attempt
+    retries: u32,
+    /// Class of the failure, if the response status or transport error
+    /// tells what went wrong
+    error_class: Option<ErrorClass>,
+}
+
+/// Take the result set of the last statement of a request
+fn last_result(results: Vec<D1QueryResult>) -> QueryResult<D1QueryResult> {
+    results.into_iter().next_back().ok_or_else(|| {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new(D1Error::new("No result returned")),
+        )
+    })
+}
+
+/// Convert a returned JSON value into its SQLite storage class
+fn json_to_bound_value(value: &serde_json::Value) -> BoundValue {
+    match value {
+        serde_json::Value::Null => BoundValue::Null,
+        serde_json::Value::Bool(b) => BoundValue::Integer(i64::from(*b)),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => BoundValue::Integer(i),
+            None => BoundValue::Float(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => BoundValue::Text(s.clone()),
+        serde_json::Value::Array(bytes) => BoundValue::Binary(
+            bytes
+                .iter()
+                .filter_map(|v| v.as_u64().map(|n| n as u8))
+                .collect(),
+        ),
+        serde_json::Value::Object(_) => BoundValue::Text(value.to_string()),
+    }
+}
+
+/// Rebuild rows from a cached result
+fn cached_rows_into_rows(rows: CachedRows) -> Vec<D1Row> {
+    let CachedRows { columns, rows } = rows;
+    rows.into_iter()
+        .map(|values| {
+            D1Row::from_values(
+                values.iter().map(BoundValue::to_json_value).collect(),
+                columns.clone(),
+            )
+        })
+        .collect()
 }
 
 /// D1 query metadata
@@ -119,6 +286,18 @@ struct D1QueryMeta {
     duration: Option<f64>,
     rows_read: Option<i64>,
     rows_written: Option<i64>,
+    last_row_id: Option<i64>,
+}
+
+impl From<D1QueryMeta> for StatementMeta {
+    fn from(meta: D1QueryMeta) -> Self {
+        Self {
+            duration_ms: meta.duration,
+            rows_read: meta.rows_read.map(|n| n as usize),
+            rows_written: meta.rows_written.map(|n| n as usize),
+            last_row_id: meta.last_row_id,
+        }
+    }
 }
 
 /// D1 Connection using the HTTP REST API
@@ -140,11 +319,28 @@ struct D1QueryMeta {
 /// let conn = D1HttpConnection::new(config);
 /// ```
 pub struct D1HttpConnection {
-    client: Client,
+    /// Delivers requests to the REST API
+    transport: Box<dyn D1Transport>,
     /// Connection configuration
     pub(crate) config: D1HttpConfig,
+    /// Write statements buffered while a transaction is open
+    transaction_queries: Vec<(String, Vec<serde_json::Value>)>,
     /// Transaction manager (public for TransactionManager trait access)
     pub(crate) transaction_manager: D1TransactionManager,
+    /// Platform limits checked before requests are sent
+    limits: D1Limits,
+    /// SQL text of static queries
+    sql_cache: SqlCache,
+    /// Results of `load_cached` queries
+    result_cache: ResultCache,
+    /// Limit on queries in flight, shared with other connections
+    concurrency_policy: Option<QueryConcurrencyPolicy>,
+    /// API request budget, shared with other connections
+    rate_limit: Option<RateLimitPolicy>,
+    /// Retry settings for failed requests
+    transport_policy: HttpTransportPolicy,
+    /// Receives a span for every query sent
+    span_emitter: Option<Arc<dyn SpanEmitter + Send + Sync>>,
     /// Instrumentation for the connection
     instrumentation: Option<Box<dyn Instrumentation>>,
 }
@@ -153,9 +349,17 @@ impl D1HttpConnection {
     /// Create a new HTTP connection with the given configuration
     pub fn new(config: D1HttpConfig) -> Self {
         Self {
-            client: Client::new(),
+            transport: Box::new(ReqwestTransport::new()),
             config,
+            transaction_queries: Vec::new(),
             transaction_manager: D1TransactionManager::default(),
+            limits: D1Limits::default(),
+            sql_cache: SqlCache::default(),
+            result_cache: ResultCache::new(ResultCacheConfig::disabled()),
+            concurrency_policy: None,
+            rate_limit: None,
+            transport_policy: HttpTransportPolicy::default(),
+            span_emitter: None,
             instrumentation: None,
         }
     }
@@ -163,92 +367,532 @@ impl D1HttpConnection {
     /// Create a new HTTP connection with a custom reqwest client
     pub fn with_client(config: D1HttpConfig, client: Client) -> Self {
         Self {
-            client,
+            transport: Box::new(ReqwestTransport::from_client(client)),
             config,
+            transaction_queries: Vec::new(),
             transaction_manager: D1TransactionManager::default(),
+            limits: D1Limits::default(),
+            sql_cache: SqlCache::default(),
+            result_cache: ResultCache::new(ResultCacheConfig::disabled()),
+            concurrency_policy: None,
+            rate_limit: None,
+            transport_policy: HttpTransportPolicy::default(),
+            span_emitter: None,
             instrumentation: None,
         }
     }
 
+    /// Create a new HTTP connection configured by a transport policy
+    ///
+    /// The client is built with the policy's timeout and pool settings,
+    /// requests are paced by its rate limit, if it has one, and failed
+    /// requests are retried as it specifies.
+    pub fn with_transport_policy(
+        config: D1HttpConfig,
+        policy: &HttpTransportPolicy,
+    ) -> Result<Self, reqwest::Error> {
+        let mut conn = Self::with_client(config, policy.create_client()?);
+        conn.rate_limit = policy.rate_limit().cloned();
+        conn.transport_policy = policy.clone();
+        Ok(conn)
+    }
+
+    /// Send requests through a custom transport instead of `reqwest`
+    ///
+    /// Retries, rate limits and payload checks still apply around the
+    /// transport. Client settings of a transport policy, such as timeouts,
+    /// are up to the transport.
+    ///
+    /// ```ignore
+    /// use std::sync::Arc;
+    /// use diesel_d1::transport::{MemoryTransport, TransportResponse};
+    ///
+    /// let transport = Arc::new(MemoryTransport::new());
+    /// transport.push_response(TransportResponse::new(200, body));
+    /// let conn = D1HttpConnection::new(config).with_transport(transport.clone());
+    /// ```
+    pub fn with_transport(mut self, transport: impl D1Transport + 'static) -> Self {
+        self.transport = Box::new(transport);
+        self
+    }
+
+    /// Set how reads issued inside a transaction are handled
+    ///
+    /// Defaults to [`TransactionReadPolicy::ReadCommitted`].
+    pub fn with_read_policy(mut self, policy: TransactionReadPolicy) -> Self {
+        self.transaction_manager.read_policy = policy;
+        self
+    }
+
+    /// Set the platform limits checked before requests are sent
+    ///
+    /// Defaults to Cloudflare's published D1 limits.
+    pub fn with_limits(mut self, limits: D1Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Enable the result cache used by [`load_cached`](Self::load_cached)
+    ///
+    /// Disabled unless configured, in which case `load_cached` always
+    /// queries D1.
+    pub fn with_result_cache(mut self, config: ResultCacheConfig) -> Self {
+        self.result_cache = ResultCache::new(config);
+        self
+    }
+
+    /// Enable the result cache used by [`load_cached`](Self::load_cached)
+    /// with a custom store
+    ///
+    /// Use an [`Arc`](std::sync::Arc) store to share results between
+    /// connections.
+    pub fn with_result_cache_store(mut self, store: impl ResultCacheStore + 'static) -> Self {
+        self.result_cache = ResultCache::with_store(store);
+        self
+    }
+
+    /// Get the hit and miss counts of the result cache
+    pub fn result_cache_stats(&self) -> CacheStatsSnapshot {
+        self.result_cache.stats()
+    }
+
+    /// Limit how many queries may be in flight at once
+    ///
+    /// Every request to D1, including batches and transaction commits, holds
+    /// a permit from the policy while it is outstanding. Pass clones of the
+    /// same policy to several connections to share one limit between them.
+    ///
+    /// Each query's outcome is reported on its permit, so an
+    /// [adaptive](crate::concurrency::AdaptiveConcurrency) policy backs off
+    /// when D1 starts rate limiting or timing out.
+    pub fn with_concurrency_policy(mut self, policy: QueryConcurrencyPolicy) -> Self {
+        self.concurrency_policy = Some(policy);
+        self
+    }
+
+    /// Pace requests against an API request budget
+    ///
+    /// Every request to the query endpoint takes one request from the
+    /// budget before it is sent. Pass clones of the same policy to every
+    /// connection using the same account.
+    pub fn with_rate_limit(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limit = Some(policy);
+        self
+    }
+
+    /// Emit a [`D1Span`](crate::D1Span) for every request sent to D1
+    pub fn with_span_emitter(mut self, emitter: Arc<dyn SpanEmitter + Send + Sync>) -> Self {
+        self.span_emitter = Some(emitter);
+        self
+    }
+
+    /// Wait for a permit from the concurrency policy, if one is set,
+    /// recording the wait on `span`
+    async fn acquire_permit(&self, span: &mut QuerySpan) -> Option<ConcurrencyPermit> {
+        let policy = self.concurrency_policy.as_ref()?;
+        let (permit, waited) = policy.acquire_timed().await;
+        span.span_mut().record_permit_wait(waited);
+        Some(permit)
+    }
+
+    /// Send any writes the read policy requires, then run a read
+    async fn read_rows(
+        &mut self,
+        sql: &str,
+        params: Vec<serde_json::Value>,
+    ) -> QueryResult<Vec<D1Row>> {
+        // Queued writes are invisible to the read unless the policy sends
+        // them first
+        if self.transaction_manager.prepare_read()? {
+            let result = self.flush_transaction_queries().await;
+            self.transaction_manager.record_outcome(result)?;
+        }
+
+        Ok(last_result(self.execute_query(sql, params).await?)?.into_rows())
+    }
+
     /// Execute a query against the D1 HTTP API
+    ///
+    /// `sql` may hold several `;`-separated statements, which yield one
+    /// result each.
     async fn execute_query(
         &self,
         sql: &str,
         params: Vec<serde_json::Value>,
-    ) -> Result<D1QueryResult, diesel::result::Error> {
+    ) -> Result<Vec<D1QueryResult>, diesel::result::Error> {
+        self.limits.check_statement(sql, params.len())?;
+
         let request = D1QueryRequest {
             sql: sql.to_string(),
             params,
         };
 
-        let response = self
-            .client
-            .post(self.config.query_url())
-            .header("Authorization", format!("Bearer {}", self.config.api_token))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
+        self.send_request(&request, SpanOperation::Execute, sql, is_read_only(sql))
             .await
-            .map_err(|e| {
-                diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::Unknown,
-                    Box::new(D1Error::new(format!("HTTP request failed: {}", e))),
-                )
-            })?;
-
-        let status = response.status();
-        let body = response.text().await.map_err(|e| {
-            diesel::result::Error::DatabaseError(
-                diesel::result::DatabaseErrorKind::Unknown,
-                Box::new(D1Error::new(format!("Failed to read response: {}", e))),
-            )
-        })?;
+    }
 
-        if !status.is_success() {
-            return Err(diesel::result::Error::DatabaseError(
-                diesel::result::DatabaseErrorKind::Unknown,
-                Box::new(D1Error::new(format!("HTTP error {}: {}", status, body))),
-            ));
+    /// Execute several statements in a single request
+    ///
+    /// D1 runs the statements as one SQL transaction, so a failure in any of
+    /// them rolls back the whole request.
+    async fn execute_batch_queries(
+        &self,
+        queries: Vec<(String, Vec<serde_json::Value>)>,
+    ) -> Result<Vec<D1QueryResult>, diesel::result::Error> {
+        self.limits.check_batch_size(queries.len())?;
+        for (sql, params) in &queries {
+            self.limits.check_statement(sql, params.len())?;
+        }
+        let idempotent = queries.iter().all(|(sql, _)| is_read_only(sql));
+        let sql = queries
+            .iter()
+            .map(|(sql, _)| sql.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        let request = D1BatchQueryRequest {
+            batch: queries
+                .into_iter()
+                .map(|(sql, params)| D1QueryRequest { sql, params })
+                .collect(),
+        };
+
+        self.send_request(&request, SpanOperation::Batch, &sql, idempotent)
+            .await
+    }
+
+    /// Execute a [`BatchBuilder`] through a single multi-statement request
+    ///
+    /// D1 runs the statements as one SQL transaction, so if any of them fails
+    /// the whole batch is rolled back and an error is returned.
+    pub async fn execute_batch(&mut self, batch: BatchBuilder) -> QueryResult<BatchResult> {
+        if batch.is_empty() {
+            return Ok(BatchResult::from_statement_results(vec![]));
+        }
+
+        for statement in batch.statements() {
+            self.result_cache.invalidate(&statement.sql).await?;
+        }
+
+        let results = self
+            .execute_batch_queries(batch_queries(batch.into_statements()))
+            .await?;
+
+        Ok(BatchResult::from_statement_results(
+            results
+                .into_iter()
+                .map(D1QueryResult::into_statement_result)
+                .collect(),
+        ))
+    }
+
+    /// Execute a script of `;`-separated statements in a single request
+    ///
+    /// Returns the outcome of every statement, including the execution
+    /// details D1 reports for it. If a statement fails, an error naming it
+    /// is returned. Like [`execute_batch`](Self::execute_batch), the script
+    /// is sent right away, even inside a transaction.
+    ///
+    /// ```ignore
+    /// let result = conn
+    ///     .execute_script("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1);")
+    ///     .await?;
+    /// assert_eq!(result.statement_results.len(), 2);
+    /// ```
+    pub async fn execute_script(&mut self, sql: &str) -> QueryResult<BatchResult> {
+        self.result_cache.invalidate(sql).await?;
+
+        let results = self.execute_query(sql, vec![]).await?;
+
+        Ok(BatchResult::from_statement_results(
+            results
+                .into_iter()
+                .map(D1QueryResult::into_statement_result)
+                .collect(),
+        ))
+    }
+
+    /// Insert many records, split into chunks that respect the connection's
+    /// bound parameter limit
+    ///
+    /// `build` turns a chunk of records into the insert statement. Every
+    /// chunk is sent in one batch, so the insert is all-or-nothing. Returns
+    /// the number of inserted rows.
+    ///
+    /// ```ignore
+    /// conn.chunked_insert(&new_users, |chunk| {
+    ///     diesel::insert_into(users::table).values(chunk)
+    /// })
+    /// .await?;
+    /// ```
+    pub async fn chunked_insert<'a, R, Q, F>(
+        &mut self,
+        records: &'a [R],
+        build: F,
+    ) -> QueryResult<usize>
+    where
+        Q: QueryFragment<D1Backend> + QueryId,
+        F: Fn(&'a [R]) -> Q,
+    {
+        let mut batch = BatchBuilder::new();
+        batch.push_chunked_insert_with_limit(records, self.limits.max_bound_parameters(), build)?;
+        Ok(self.execute_batch(batch).await?.total_rows_affected)
+    }
+
+    /// Load several queries through a single multi-statement request
+    ///
+    /// Takes a tuple of queries and returns a tuple with one `Vec` of decoded
+    /// rows per query, in the same order.
+    ///
+    /// ```ignore
+    /// let (users, posts): (Vec<User>, Vec<Post>) = conn
+    ///     .batch_load((users::table.limit(10), posts::table.limit(10)))
+    ///     .await?;
+    /// ```
+    pub async fn batch_load<Q, Output>(&mut self, queries: Q) -> QueryResult<Output>
+    where
+        Q: BatchLoad<Output>,
+    {
+        // Queued writes are invisible to the reads unless the policy sends
+        // them first
+        if self.transaction_manager.prepare_read()? {
+            let result = self.flush_transaction_queries().await;
+            self.transaction_manager.record_outcome(result)?;
+        }
+
+        let results = self
+            .execute_batch_queries(batch_queries(queries.into_statements()?))
+            .await?;
+
+        Q::decode_result_sets(results.into_iter().map(D1QueryResult::into_rows).collect())
+    }
+
+    /// Load a query, serving its rows from the result cache for up to `ttl`
+    ///
+    /// Results are keyed by SQL plus binds. Writes issued through this
+    /// connection invalidate the cached results of the tables they touch;
+    /// writes made elsewhere become visible once the TTL expires. Results
+    /// read inside a transaction are not cached.
+    ///
+    /// ```ignore
+    /// let settings: Vec<Setting> = conn
+    ///     .load_cached(settings::table, Duration::from_secs(60))
+    ///     .await?;
+    /// ```
+    pub async fn load_cached<Q, U>(&mut self, query: Q, ttl: Duration) -> QueryResult<Vec<U>>
+    where
+        Q: AsQuery,
+        Q::Query: QueryFragment<D1Backend> + QueryId,
+        U: FromSqlRow<Q::SqlType, D1Backend>,
+    {
+        // Queued writes are invisible to the read unless the policy sends
+        // them first
+        if self.transaction_manager.prepare_read()? {
+            let result = self.flush_transaction_queries().await;
+            self.transaction_manager.record_outcome(result)?;
+        }
+
+        let statement = render_query(query.as_query())?;
+        let key = result_cache_key(&statement);
+        if let Some(rows) = self.result_cache.get(&key).await {
+            return decode_rows::<U, Q::SqlType, _>(Some(cached_rows_into_rows(rows)));
+        }
+
+        let params = statement.params.iter().map(|v| v.to_json_value()).collect();
+        let result = last_result(self.execute_query(&statement.sql, params).await?)?;
+        if self.result_cache.enabled() && !self.transaction_manager.is_in_transaction() {
+            self.result_cache
+                .insert(key, &statement.sql, &result.to_cached_rows(), ttl)
+                .await;
+        }
+
+        decode_rows::<U, Q::SqlType, _>(Some(result.into_rows()))
+    }
+
+    /// Send the buffered transaction statements as one batch and empty the queue
+    pub(crate) async fn flush_transaction_queries(&mut self) -> QueryResult<()> {
+        let queries = std::mem::take(&mut self.transaction_queries);
+        self.transaction_manager.reset_savepoints();
+        if !queries.is_empty() {
+            self.execute_batch_queries(queries).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a request body to the query endpoint and return every result set
+    ///
+    /// Every request the connection makes goes through here. It holds a
+    /// permit from the concurrency policy while outstanding and emits a span
+    /// for `sql` recording its retries, rows and outcome.
+    async fn send_request<B: Serialize>(
+        &self,
+        request: &B,
+        operation: SpanOperation,
+        sql: &str,
+        idempotent: bool,
+    ) -> Result<Vec<D1QueryResult>, diesel::result::Error> {
+        let payload = serde_json::to_vec(request)
+            .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+        self.limits.check_payload(payload.len())?;
+
+        let mut span = QuerySpan::start(
+            operation,
+            BackendType::Http,
+            Some(sql),
+            self.span_emitter.as_ref(),
+        );
+        let permit = self.acquire_permit(&mut span).await;
+        let mut attempts = RequestAttempts::default();
+        let result = self.send_payload(payload, idempotent, &mut attempts).await;
+        if let Ok(results) = &result {
+            span.span_mut().rows_read = Some(results.iter().map(D1QueryResult::row_count).sum());
+            span.span_mut().rows_written = Some(results.iter().map(D1QueryResult::changes).sum());
+        }
+        span.span_mut().record_retry(attempts.retries);
+        if let Some(permit) = &permit {
+            permit.record_result(&result, attempts.error_class);
+        }
+        span.finish(&result);
+        result
+    }
+
+    /// Send a serialized request body, retrying transient failures, and
+    /// return every result set
+    ///
+    /// Fails if any statement of the request failed.
+    ///
+    /// If the transport policy enables retries, responses with a transient
+    /// status and transport failures such as timeouts or dropped connections
+    /// are retried with exponential backoff, or after the delay a
+    /// `Retry-After` header asks for. Requests that are not `idempotent` are
+    /// only retried if the policy opts in to retrying writes. Retries made
+    /// are counted in `attempts`, which also records the class of a failure
+    /// as told by the response status or the transport error.
+    async fn send_payload(
+        &self,
+        payload: Vec<u8>,
+        idempotent: bool,
+        attempts: &mut RequestAttempts,
+    ) -> Result<Vec<D1QueryResult>, diesel::result::Error> {
+        let policy = &self.transport_policy;
+        let retryable = policy.retry_enabled() && (idempotent || policy.retry_writes());
+
+        let response = loop {
+            // Every attempt counts against the request budget
+            if let Some(rate_limit) = &self.rate_limit {
+                rate_limit.acquire().await?;
+            }
+
+            let request = TransportRequest {
+                url: self.config.raw_query_url(),
+                headers: vec![
+                    (
+                        "Authorization".to_string(),
+                        format!("Bearer {}", self.config.api_token),
+                    ),
+                    ("Content-Type".to_string(), "application/json".to_string()),
+                ],
+                body: payload.clone(),
+            };
+            let sent = self.transport.send(request).await;
+            let error_class = match &sent {
+                Ok(response) => ErrorClass::from_http_status(response.status),
+                Err(e) => transport_error_class(e.as_ref()),
+            };
+
+            if !retryable || attempts.retries >= policy.max_retries() || !error_class.is_transient()
+            {
+                break sent.map_err(|e| {
+                    attempts.error_class = Some(error_class);
+                    diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::Unknown,
+                        Box::new(D1Error::new(format!("HTTP request failed: {}", e))),
+                    )
+                })?;
+            }
+
+            // Without a response there is no `Retry-After` to honour
+            let delay = sent.ok().as_ref().and_then(retry_after).unwrap_or_else(|| {
+                backoff_delay(policy.retry_base_delay(), attempts.retries, jitter())
+            });
+            attempts.retries += 1;
+            tokio::time::sleep(delay).await;
+        };
+
+        let body = String::from_utf8_lossy(&response.body);
+
+        if !response.is_success() {
+            let error_class = ErrorClass::from_http_status(response.status);
+            attempts.error_class = Some(error_class).filter(|class| *class != ErrorClass::Unknown);
+
+            // Name the status as reqwest does, e.g. "503 Service Unavailable"
+            let status = StatusCode::from_u16(response.status)
+                .map_or_else(|_| response.status.to_string(), |status| status.to_string());
+            return Err(d1_database_error(format!(
+                "HTTP error {}: {}",
+                status, body
+            )));
         }
 
         let api_response: D1ApiResponse = serde_json::from_str(&body).map_err(|e| {
+            attempts.error_class = Some(ErrorClass::Decode);
             diesel::result::Error::DatabaseError(
                 diesel::result::DatabaseErrorKind::Unknown,
                 Box::new(D1Error::new(format!("Failed to parse response: {}", e))),
             )
         })?;
 
+        // Name the failed statement when D1 reports which one it was
+        if let Some(error_msg) = api_response.result.as_deref().and_then(failed_statement) {
+            attempts.error_class = Some(ErrorClass::SqlError);
+            return Err(d1_database_error(error_msg));
+        }
+
         if !api_response.success {
+            attempts.error_class = Some(ErrorClass::SqlError);
             let error_msg = api_response
                 .errors
                 .first()
                 .map(|e| e.message.clone())
                 .unwrap_or_else(|| "Unknown error".to_string());
-            return Err(diesel::result::Error::DatabaseError(
-                diesel::result::DatabaseErrorKind::Unknown,
-                Box::new(D1Error::new(error_msg)),
-            ));
+            return Err(d1_database_error(error_msg));
         }
 
-        api_response
-            .result
-            .and_then(|r| r.into_iter().next())
-            .ok_or_else(|| {
-                diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::Unknown,
-                    Box::new(D1Error::new("No result returned")),
-                )
-            })
+        api_response.result.ok_or_else(|| {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::Unknown,
+                Box::new(D1Error::new("No result returned")),
+            )
+        })
     }
 }
 
-// SAFETY: The HTTP client and configuration are thread-safe
+impl D1HttpTransactionConnection for D1HttpConnection {
+    fn d1_transaction_manager(&mut self) -> &mut D1TransactionManager {
+        &mut self.transaction_manager
+    }
+
+    fn transaction_queries(&mut self) -> &mut Vec<(String, Vec<serde_json::Value>)> {
+        &mut self.transaction_queries
+    }
+}
+
+// SAFETY: The transport and configuration are thread-safe
 unsafe impl Send for D1HttpConnection {}
 unsafe impl Sync for D1HttpConnection {}
 
 #[async_trait]
 impl SimpleAsyncConnection for D1HttpConnection {
     async fn batch_execute(&mut self, query: &str) -> diesel::QueryResult<()> {
+        self.limits.check_statement(query, 0)?;
+        self.result_cache.invalidate(query).await?;
+
+        // Inside a transaction the statement is buffered and sent on commit
+        if self.transaction_manager.is_in_transaction() {
+            self.transaction_queries.push((query.to_string(), vec![]));
+            return Ok(());
+        }
+
         self.execute_query(query, vec![]).await?;
         Ok(())
     }
@@ -332,35 +976,12 @@ impl AsyncConnection for D1HttpConnection {
         T::Query: QueryFragment<Self::Backend> + QueryId + 'query,
     {
         let source = source.as_query();
-        let (sql, params) = build_query_with_params(source);
+        let result = build_query_with_params(source, &mut self.sql_cache);
 
         async move {
-            let result = self.execute_query(&sql, params).await?;
-
-            let results = result.results.unwrap_or_default();
-
-            if results.is_empty() {
-                return Ok(stream::iter(vec![]).boxed());
-            }
-
-            // Get field names from first result
-            // Sort keys to ensure consistent field ordering regardless of JSON object iteration order
-            let field_keys: Vec<String> = if let Some(first) = results.first() {
-                if let Some(obj) = first.as_object() {
-                    let mut keys: Vec<String> = obj.keys().cloned().collect();
-                    keys.sort();
-                    keys
-                } else {
-                    vec![]
-                }
-            } else {
-                vec![]
-            };
-
-            let rows: Vec<QueryResult<D1Row>> = results
-                .into_iter()
-                .map(|val| Ok(D1Row::new(val, field_keys.clone())))
-                .collect();
+            let (sql, params) = result?;
+            let rows = self.read_rows(&sql, params).await?;
+            let rows: Vec<QueryResult<D1Row>> = rows.into_iter().map(Ok).collect();
 
             Ok(stream::iter(rows).boxed())
         }
@@ -374,14 +995,22 @@ impl AsyncConnection for D1HttpConnection {
     where
         T: QueryFragment<Self::Backend> + QueryId + 'query,
     {
-        let (sql, params) = build_query_with_params(source);
+        let result = build_query_with_params(source, &mut self.sql_cache);
 
         async move {
-            let result = self.execute_query(&sql, params).await?;
-
-            let changes = result.meta.and_then(|m| m.changes).unwrap_or(0);
+            let (sql, params) = result?;
+            self.result_cache.invalidate(&sql).await?;
+
+            // Writes inside a transaction are buffered and sent as one atomic
+            // batch on commit, so the affected row count is not known yet
+            if self.transaction_manager.is_in_transaction() {
+                self.limits.check_statement(&sql, params.len())?;
+                self.transaction_queries.push((sql, params));
+                return Ok(0);
+            }
 
-            Ok(changes as usize)
+            let results = self.execute_query(&sql, params).await?;
+            Ok(results.iter().map(D1QueryResult::changes).sum())
         }
         .boxed()
     }
@@ -411,18 +1040,32 @@ impl Instrumentation for NoopInstrumentation {
     }
 }
 
+/// Convert batch statements into SQL and JSON parameters
+fn batch_queries(statements: Vec<BatchStatement>) -> Vec<(String, Vec<serde_json::Value>)> {
+    statements
+        .into_iter()
+        .map(|statement| {
+            let params = statement.params.iter().map(|v| v.to_json_value()).collect();
+            (statement.sql, params)
+        })
+        .collect()
+}
+
 /// Build SQL and parameters from a query
-fn build_query_with_params<T>(source: T) -> (String, Vec<serde_json::Value>)
+///
+/// The SQL of static queries comes from `sql_cache`, so only the binds are
+/// collected on repeated calls.
+fn build_query_with_params<T>(
+    source: T,
+    sql_cache: &mut SqlCache,
+) -> QueryResult<(String, Vec<serde_json::Value>)>
 where
-    T: QueryFragment<D1Backend>,
+    T: QueryFragment<D1Backend> + QueryId,
 {
-    let mut query_builder = D1QueryBuilder::default();
-    source.to_sql(&mut query_builder, &D1Backend).unwrap();
+    let sql = sql_cache.sql(&source)?;
 
     let mut bind_collector = D1BindCollector::default();
-    source
-        .collect_binds(&mut bind_collector, &mut (), &D1Backend)
-        .unwrap();
+    source.collect_binds(&mut bind_collector, &mut (), &D1Backend)?;
 
     let params: Vec<serde_json::Value> = bind_collector
         .binds
@@ -430,7 +1073,41 @@ where
         .map(|(bind, _)| bind.to_json_value())
         .collect();
 
-    (query_builder.sql, params)
+    Ok((sql, params))
+}
+
+/// Read the delay a response asks for in its `Retry-After` header
+///
+/// Only the delta-seconds form is understood; an HTTP date is ignored.
+fn retry_after(response: &TransportResponse) -> Option<Duration> {
+    parse_retry_after(response.header("Retry-After")?)
+}
+
+/// Parse a `Retry-After` value given in seconds
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Delay before retry number `retry` (counting from zero)
+///
+/// The delay doubles with every retry and is scaled into
+/// `[delay / 2, delay)` by `jitter`, a fraction in `[0, 1)`, so that
+/// clients failing together don't retry together.
+fn backoff_delay(base: Duration, retry: u32, jitter: f64) -> Duration {
+    let delay = base.saturating_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX));
+    delay.mul_f64(0.5 + jitter / 2.0)
+}
+
+/// A random fraction in `[0, 1)` for backoff jitter
+fn jitter() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    // Every `RandomState` is keyed differently, so hashing nothing with a
+    // fresh one yields a new random value
+    let hash = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (hash >> 11) as f64 / (1u64 << 53) as f64
 }
 
 /// Simple percent-decode for URL parsing
@@ -465,6 +1142,7 @@ fn percent_decode(input: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::MemoryTransport;
 
     #[test]
     fn test_d1_http_config_new() {
@@ -476,12 +1154,12 @@ mod tests {
     }
 
     #[test]
-    fn test_d1_http_config_query_url() {
+    fn test_d1_http_config_raw_query_url() {
         let config = D1HttpConfig::new("acc123", "db456", "token");
-        let url = config.query_url();
+        let url = config.raw_query_url();
         assert!(url.contains("acc123"));
         assert!(url.contains("db456"));
-        assert!(url.ends_with("/query"));
+        assert!(url.ends_with("/raw"));
     }
 
     #[test]
@@ -491,6 +1169,142 @@ mod tests {
         assert_eq!(config.base_url, "http://localhost:8080");
     }
 
+    #[test]
+    fn test_query_result_into_statement_result() {
+        let result: D1QueryResult = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "results": { "columns": [], "rows": [] },
+            "meta": { "changes": 3, "duration": 0.25, "rows_written": 3, "last_row_id": 9 }
+        }))
+        .unwrap();
+        let result = result.into_statement_result();
+        assert!(result.success);
+        assert_eq!(result.rows_affected, 3);
+        let meta = result.meta.unwrap();
+        assert_eq!(meta.duration_ms, Some(0.25));
+        assert_eq!(meta.rows_written, Some(3));
+        assert_eq!(meta.rows_read, None);
+        assert_eq!(meta.last_row_id, Some(9));
+
+        let result: D1QueryResult = serde_json::from_value(serde_json::json!({
+            "success": false,
+            "error": "no such table: t"
+        }))
+        .unwrap();
+        let result = result.into_statement_result();
+        assert!(!result.success);
+        assert_eq!(result.error, Some("no such table: t".to_string()));
+    }
+
+    #[test]
+    fn test_failed_statement_names_first_failure() {
+        let results: Vec<D1QueryResult> = serde_json::from_value(serde_json::json!([
+            { "success": true },
+            { "success": false, "error": "no such table: t" },
+            { "success": false, "error": "not reached" }
+        ]))
+        .unwrap();
+        assert_eq!(
+            failed_statement(&results).as_deref(),
+            Some("Statement 2 of 3 failed: no such table: t")
+        );
+        assert_eq!(failed_statement(&results[..1]), None);
+    }
+
+    #[test]
+    fn test_query_result_rows_keep_column_order() {
+        use diesel::row::{Field, Row};
+
+        let result: D1QueryResult = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "results": {
+                "columns": ["name", "id", "created_at"],
+                "rows": [["Alice", 1, "2024-01-01"]]
+            }
+        }))
+        .unwrap();
+
+        let rows = result.into_rows();
+        assert_eq!(rows.len(), 1);
+        let names: Vec<_> = (0..rows[0].field_count())
+            .map(|i| rows[0].get(i).unwrap().field_name().unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["name", "id", "created_at"]);
+    }
+
+    #[test]
+    fn test_query_result_rows_keep_duplicate_columns() {
+        use diesel::row::{Field, Row};
+
+        // `SELECT users.id, users.name, posts.id, posts.title` over a join
+        let result: D1QueryResult = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "results": {
+                "columns": ["id", "name", "id", "title"],
+                "rows": [[1, "Alice", 7, "Hello"]]
+            }
+        }))
+        .unwrap();
+
+        let rows = result.into_rows();
+        assert_eq!(rows[0].field_count(), 4);
+        let post_id = rows[0].get(2usize).unwrap().value().unwrap();
+        assert!((post_id.read_number() - 7.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_query_result_to_cached_rows() {
+        use diesel::row::Row;
+
+        let result: D1QueryResult = serde_json::from_value(serde_json::json!({
+            "success": true,
+            "results": {
+                "columns": ["name", "id", "score", "avatar"],
+                "rows": [
+                    ["Alice", 1, 1.5, [1, 2]],
+                    [null, 2, 2, null]
+                ]
+            }
+        }))
+        .unwrap();
+
+        let rows = result.to_cached_rows();
+        assert_eq!(rows.columns, vec!["name", "id", "score", "avatar"]);
+        assert_eq!(
+            rows.rows,
+            vec![
+                vec![
+                    BoundValue::Text("Alice".to_string()),
+                    BoundValue::Integer(1),
+                    BoundValue::Float(1.5),
+                    BoundValue::Binary(vec![1, 2]),
+                ],
+                vec![
+                    BoundValue::Null,
+                    BoundValue::Integer(2),
+                    BoundValue::Integer(2),
+                    BoundValue::Null,
+                ],
+            ]
+        );
+
+        let rebuilt = cached_rows_into_rows(rows);
+        assert_eq!(rebuilt.len(), 2);
+        assert_eq!(rebuilt[0].field_count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_execute_empty_batch_sends_nothing() {
+        let transport = Arc::new(MemoryTransport::new());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config).with_transport(transport.clone());
+
+        let result = conn.execute_batch(BatchBuilder::new()).await.unwrap();
+        assert!(result.success);
+        assert!(result.statement_results.is_empty());
+        assert!(transport.requests().is_empty());
+    }
+
     #[tokio::test]
     async fn test_establish_valid_url() {
         let result = D1HttpConnection::establish("d1://account:token@database").await;
@@ -551,6 +1365,644 @@ mod tests {
         assert_eq!(conn.config.api_token, "token@with:special");
     }
 
+    #[tokio::test]
+    async fn test_transaction_buffers_writes() {
+        use diesel_async::TransactionManager;
+
+        let mut conn = D1HttpConnection::new(D1HttpConfig::new("account", "database", "token"));
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Alice')")
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Bob')")
+            .await
+            .unwrap();
+        assert_eq!(conn.transaction_queries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_rollback_drops_queue() {
+        use diesel_async::TransactionManager;
+
+        let mut conn = D1HttpConnection::new(D1HttpConfig::new("account", "database", "token"));
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Alice')")
+            .await
+            .unwrap();
+
+        D1TransactionManager::rollback_transaction(&mut conn)
+            .await
+            .unwrap();
+        assert!(conn.transaction_queries.is_empty());
+        assert!(!conn.transaction_manager.is_in_transaction());
+    }
+
+    #[tokio::test]
+    async fn test_nested_rollback_drops_only_inner_statements() {
+        use diesel_async::TransactionManager;
+
+        let mut conn = D1HttpConnection::new(D1HttpConfig::new("account", "database", "token"));
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Alice')")
+            .await
+            .unwrap();
+
+        // Inner level that is committed keeps its statement
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Bob')")
+            .await
+            .unwrap();
+        D1TransactionManager::commit_transaction(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(conn.transaction_queries.len(), 2);
+
+        // Inner level that is rolled back drops only its own statements
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Carol')")
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Dave')")
+            .await
+            .unwrap();
+        D1TransactionManager::rollback_transaction(&mut conn)
+            .await
+            .unwrap();
+
+        assert_eq!(conn.transaction_manager.transaction_depth(), 1);
+        assert!(conn.transaction_manager.is_in_transaction());
+        let queued: Vec<&str> = conn
+            .transaction_queries
+            .iter()
+            .map(|(sql, _)| sql.as_str())
+            .collect();
+        assert_eq!(
+            queued,
+            vec![
+                "INSERT INTO users (name) VALUES ('Alice')",
+                "INSERT INTO users (name) VALUES ('Bob')",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_policy_reported_by_transaction_manager() {
+        let mut conn = D1HttpConnection::new(D1HttpConfig::new("account", "database", "token"));
+        assert_eq!(
+            conn.transaction_state().read_policy(),
+            TransactionReadPolicy::ReadCommitted
+        );
+
+        let mut conn = conn.with_read_policy(TransactionReadPolicy::FlushAndContinue);
+        assert_eq!(
+            conn.transaction_state().read_policy(),
+            TransactionReadPolicy::FlushAndContinue
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits_checked_before_sending() {
+        use crate::limits::{LimitExceeded, LimitKind};
+
+        let transport = Arc::new(MemoryTransport::new());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let limits = D1Limits::builder()
+            .max_sql_length(16)
+            .max_batch_statements(1)
+            .build();
+        let mut conn = D1HttpConnection::new(config)
+            .with_transport(transport.clone())
+            .with_limits(limits);
+
+        let error = conn
+            .batch_execute("SELECT * FROM a_rather_long_table_name")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            LimitExceeded::from_error(&error).map(|e| e.kind),
+            Some(LimitKind::SqlLength)
+        );
+
+        let mut batch = BatchBuilder::new();
+        batch.add_raw("SELECT 1");
+        batch.add_raw("SELECT 2");
+        let error = conn.execute_batch(batch).await.unwrap_err();
+        assert_eq!(
+            LimitExceeded::from_error(&error).map(|e| e.kind),
+            Some(LimitKind::BatchStatements)
+        );
+        assert!(transport.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_empty_transaction_commit_sends_nothing() {
+        use diesel_async::TransactionManager;
+
+        let transport = Arc::new(MemoryTransport::new());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config).with_transport(transport.clone());
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        D1TransactionManager::commit_transaction(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(conn.transaction_manager.transaction_depth(), 0);
+        assert!(transport.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_breaks_transaction_manager() {
+        use diesel_async::TransactionManager;
+
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_error("connection reset");
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config).with_transport(transport.clone());
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("INSERT INTO users (name) VALUES ('Alice')")
+            .await
+            .unwrap();
+        assert!(D1TransactionManager::commit_transaction(&mut conn)
+            .await
+            .is_err());
+        assert_eq!(transport.requests().len(), 1);
+
+        assert!(D1TransactionManager::is_broken_transaction_manager(
+            &mut conn
+        ));
+        assert!(matches!(
+            D1TransactionManager::begin_transaction(&mut conn).await,
+            Err(diesel::result::Error::BrokenTransactionManager)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_query_holds_concurrency_permit() {
+        use crate::tracing_support::TestSpanEmitter;
+
+        let transport = scripted_transport(vec![success()]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = QueryConcurrencyPolicy::new(1);
+        let emitter = Arc::new(TestSpanEmitter::new());
+        let mut conn = D1HttpConnection::new(config)
+            .with_transport(transport.clone())
+            .with_concurrency_policy(policy.clone())
+            .with_span_emitter(emitter.clone());
+
+        let held = policy.try_acquire().unwrap();
+        let query = tokio::spawn(async move { conn.batch_execute("DELETE FROM users").await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(policy.waiting(), 1);
+        assert!(emitter.is_empty());
+        assert!(transport.requests().is_empty());
+
+        drop(held);
+        query.await.unwrap().unwrap();
+        assert_eq!(transport.requests().len(), 1);
+        assert_eq!(policy.current_in_flight(), 0);
+
+        let spans = emitter.get_spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].sql.as_deref(), Some("DELETE FROM users"));
+        assert!(spans[0].permit_wait.unwrap() >= Duration::from_millis(20));
+        assert_eq!(spans[0].rows_written, Some(1));
+        assert!(!spans[0].is_error());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejects_before_sending() {
+        use crate::rate_limit::{RateLimitBehavior, RateLimited};
+
+        let transport = Arc::new(MemoryTransport::new());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let rate_limit = RateLimitPolicy::builder()
+            .requests_per_window(1)
+            .window(Duration::from_secs(60))
+            .behavior(RateLimitBehavior::FailFast)
+            .build();
+        let policy = HttpTransportPolicy::builder()
+            .rate_limit(rate_limit.clone())
+            .build();
+        let mut conn = D1HttpConnection::with_transport_policy(config, &policy)
+            .unwrap()
+            .with_transport(transport.clone());
+
+        rate_limit.try_acquire().unwrap();
+        let err = conn.batch_execute("DELETE FROM users").await.unwrap_err();
+        assert!(RateLimited::from_error(&err).is_some());
+        assert!(transport.requests().is_empty());
+    }
+
+    /// A transport answering with `responses` in order
+    fn scripted_transport(responses: Vec<TransportResponse>) -> Arc<MemoryTransport> {
+        let transport = Arc::new(MemoryTransport::new());
+        for response in responses {
+            transport.push_response(response);
+        }
+        transport
+    }
+
+    /// A transient failure asking to be retried right away
+    fn unavailable() -> TransportResponse {
+        TransportResponse::new(503, "service unavailable").with_header("Retry-After", "0")
+    }
+
+    const SUCCESS: &str = r#"{"success":true,"errors":[],"result":[{"success":true,"results":{"columns":[],"rows":[]},"meta":{"changes":1}}]}"#;
+
+    fn success() -> TransportResponse {
+        TransportResponse::new(200, SUCCESS)
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("0"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after(" 30 "), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn test_backoff_delay_doubles_with_jitter() {
+        let base = Duration::from_millis(100);
+        assert_eq!(backoff_delay(base, 0, 0.0), Duration::from_millis(50));
+        assert_eq!(backoff_delay(base, 0, 0.5), Duration::from_millis(75));
+        assert_eq!(backoff_delay(base, 3, 0.0), Duration::from_millis(400));
+        assert!(backoff_delay(base, 3, 0.999) < Duration::from_millis(800));
+
+        // Large retry counts saturate instead of overflowing
+        assert!(backoff_delay(base, 40, 0.0) > Duration::from_secs(1_000_000));
+
+        for _ in 0..100 {
+            let jitter = jitter();
+            assert!((0.0..1.0).contains(&jitter));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_retried_on_transient_status() {
+        use crate::tracing_support::TestSpanEmitter;
+
+        let transport = scripted_transport(vec![unavailable(), unavailable(), success()]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = HttpTransportPolicy::builder()
+            .retry_enabled(true)
+            .max_retries(3)
+            .retry_base_delay(Duration::from_millis(1))
+            .build();
+        let emitter = Arc::new(TestSpanEmitter::new());
+        let mut conn = D1HttpConnection::with_transport_policy(config, &policy)
+            .unwrap()
+            .with_transport(transport.clone())
+            .with_span_emitter(emitter.clone());
+
+        conn.batch_execute("SELECT * FROM users").await.unwrap();
+        assert_eq!(transport.requests().len(), 3);
+
+        let spans = emitter.get_spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].retry_count, Some(2));
+        assert!(!spans[0].is_error());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_overrides_backoff() {
+        // The backoff would wait a minute; the header asks for no delay
+        let transport = scripted_transport(vec![unavailable(), success()]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = HttpTransportPolicy::builder()
+            .retry_enabled(true)
+            .retry_base_delay(Duration::from_secs(60))
+            .build();
+        let mut conn = D1HttpConnection::with_transport_policy(config, &policy)
+            .unwrap()
+            .with_transport(transport.clone());
+
+        tokio::time::timeout(Duration::from_secs(5), conn.batch_execute("SELECT 1"))
+            .await
+            .expect("retry should honour Retry-After")
+            .unwrap();
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_max_retries() {
+        let transport = scripted_transport(vec![unavailable(), unavailable(), unavailable()]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = HttpTransportPolicy::builder()
+            .retry_enabled(true)
+            .max_retries(1)
+            .retry_base_delay(Duration::from_millis(1))
+            .build();
+        let mut conn = D1HttpConnection::with_transport_policy(config, &policy)
+            .unwrap()
+            .with_transport(transport.clone());
+
+        let err = conn.batch_execute("SELECT 1").await.unwrap_err();
+        assert!(err.to_string().contains("503 Service Unavailable"));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_writes_retried_only_on_opt_in() {
+        let connection = |retry_writes| {
+            let transport = scripted_transport(vec![unavailable(), success()]);
+            let config = D1HttpConfig::new("account", "database", "token");
+            let policy = HttpTransportPolicy::builder()
+                .retry_enabled(true)
+                .max_retries(3)
+                .retry_base_delay(Duration::from_millis(1))
+                .retry_writes(retry_writes)
+                .build();
+            let conn = D1HttpConnection::with_transport_policy(config, &policy)
+                .unwrap()
+                .with_transport(transport.clone());
+            (conn, transport)
+        };
+
+        let (mut conn, transport) = connection(false);
+        assert!(conn.batch_execute("DELETE FROM users").await.is_err());
+        assert_eq!(transport.requests().len(), 1);
+
+        let (mut conn, transport) = connection(true);
+        conn.batch_execute("DELETE FROM users").await.unwrap();
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_script_reports_every_statement() {
+        let transport = scripted_transport(vec![TransportResponse::new(
+            200,
+            r#"{"success":true,"errors":[],"result":[
+                {"success":true,"results":{"columns":[],"rows":[]},"meta":{"changes":0}},
+                {"success":true,"results":{"columns":[],"rows":[]},"meta":{"changes":2,"last_row_id":2}}
+            ]}"#,
+        )]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config).with_transport(transport);
+
+        let result = conn
+            .execute_script("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1), (2);")
+            .await
+            .unwrap();
+        assert_eq!(result.statement_results.len(), 2);
+        assert_eq!(result.total_rows_affected, 2);
+        assert_eq!(
+            result.statement_results[1].meta.unwrap().last_row_id,
+            Some(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_script_error_names_failed_statement() {
+        let transport = scripted_transport(vec![TransportResponse::new(
+            200,
+            r#"{"success":false,"errors":[{"code":7500,"message":"no such table: t"}],"result":[
+                {"success":true,"meta":{"changes":1}},
+                {"success":false,"error":"no such table: t"}
+            ]}"#,
+        )]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config).with_transport(transport);
+
+        let err = conn
+            .batch_execute("INSERT INTO users (name) VALUES ('a'); DELETE FROM t;")
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Statement 2 of 2 failed: no such table: t"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_sent_through_transport() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response(success());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config).with_transport(transport.clone());
+
+        conn.batch_execute("DELETE FROM users").await.unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0]
+            .url
+            .ends_with("/accounts/account/d1/database/database/raw"));
+        assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body, serde_json::json!({ "sql": "DELETE FROM users" }));
+    }
+
+    #[tokio::test]
+    async fn test_chunked_insert_uses_configured_limit() {
+        use diesel::prelude::*;
+
+        diesel::table! {
+            users (id) {
+                id -> Integer,
+                name -> Text,
+            }
+        }
+
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response(success());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let limits = D1Limits::builder().max_bound_parameters(4).build();
+        let mut conn = D1HttpConnection::new(config)
+            .with_limits(limits)
+            .with_transport(transport.clone());
+
+        let records: Vec<_> = (0..5)
+            .map(|i| (users::id.eq(i), users::name.eq("user")))
+            .collect();
+        conn.chunked_insert(&records, |chunk| {
+            diesel::insert_into(users::table).values(chunk.to_vec())
+        })
+        .await
+        .unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let params: Vec<usize> = body["batch"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|statement| statement["params"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(params, vec![4, 4, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_transport_retries_and_errors() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response(TransportResponse::new(429, "").with_header("Retry-After", "0"));
+        transport.push_response(success());
+        transport.push_error("connection reset");
+        transport.push_response(success());
+        transport.push_error("connection reset");
+        transport.push_error("connection reset");
+        transport.push_error("connection reset");
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = HttpTransportPolicy::builder()
+            .retry_enabled(true)
+            .max_retries(2)
+            .retry_base_delay(Duration::from_millis(1))
+            .build();
+        let mut conn = D1HttpConnection::with_transport_policy(config, &policy)
+            .unwrap()
+            .with_transport(transport.clone());
+
+        conn.batch_execute("SELECT 1").await.unwrap();
+        assert_eq!(transport.requests().len(), 2);
+
+        // Failures to get any response are retried like a transient status
+        conn.batch_execute("SELECT 1").await.unwrap();
+        assert_eq!(transport.requests().len(), 4);
+
+        let err = conn.batch_execute("SELECT 1").await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("HTTP request failed: connection reset"));
+        assert_eq!(transport.requests().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_flushed_batch_retries_reach_span() {
+        use crate::tracing_support::TestSpanEmitter;
+        use diesel_async::TransactionManager;
+
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_error("connection reset");
+        transport.push_response(success());
+        transport.push_response(success());
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = HttpTransportPolicy::builder()
+            .retry_enabled(true)
+            .retry_writes(true)
+            .retry_base_delay(Duration::from_millis(1))
+            .build();
+        let emitter = Arc::new(TestSpanEmitter::new());
+        let mut conn = D1HttpConnection::with_transport_policy(config, &policy)
+            .unwrap()
+            .with_transport(transport.clone())
+            .with_read_policy(TransactionReadPolicy::FlushAndContinue)
+            .with_span_emitter(emitter.clone());
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("DELETE FROM users").await.unwrap();
+        assert!(conn.load(diesel::sql_query("SELECT 1")).await.is_ok());
+        assert_eq!(transport.requests().len(), 3);
+
+        // The flush the read triggered gets its own span with its retry
+        let spans = emitter.get_spans();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].operation, SpanOperation::Batch);
+        assert_eq!(spans[0].sql.as_deref(), Some("DELETE FROM users"));
+        assert_eq!(spans[0].retry_count, Some(1));
+        assert_eq!(spans[0].rows_written, Some(1));
+        assert_eq!(spans[1].operation, SpanOperation::Execute);
+        assert_eq!(spans[1].retry_count, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_commit_and_batches_hold_concurrency_permit() {
+        use crate::tracing_support::TestSpanEmitter;
+        use diesel_async::TransactionManager;
+
+        let transport = scripted_transport(vec![success(), success(), success()]);
+        let config = D1HttpConfig::new("account", "database", "token");
+        let policy = QueryConcurrencyPolicy::new(1);
+        let emitter = Arc::new(TestSpanEmitter::new());
+        let mut conn = D1HttpConnection::new(config)
+            .with_transport(transport.clone())
+            .with_concurrency_policy(policy.clone())
+            .with_span_emitter(emitter.clone());
+
+        D1TransactionManager::begin_transaction(&mut conn)
+            .await
+            .unwrap();
+        conn.batch_execute("DELETE FROM users").await.unwrap();
+        let mut batch = BatchBuilder::new();
+        batch.add_raw("DELETE FROM posts");
+        batch.add_raw("DELETE FROM tags");
+
+        let held = policy.try_acquire().unwrap();
+        let task = tokio::spawn(async move {
+            D1TransactionManager::commit_transaction(&mut conn).await?;
+            conn.execute_batch(batch).await?;
+            conn.execute_script("DELETE FROM users").await
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(policy.waiting(), 1);
+        assert!(transport.requests().is_empty());
+
+        drop(held);
+        task.await.unwrap().unwrap();
+        assert_eq!(transport.requests().len(), 3);
+        assert_eq!(policy.current_in_flight(), 0);
+
+        let spans = emitter.get_spans();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].operation, SpanOperation::Batch);
+        assert!(spans[0].permit_wait.unwrap() >= Duration::from_millis(20));
+        assert_eq!(
+            spans[1].sql.as_deref(),
+            Some("DELETE FROM posts; DELETE FROM tags")
+        );
+        assert_eq!(spans[2].operation, SpanOperation::Execute);
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_limit_classifies_errors_by_status() {
+        use crate::concurrency::AdaptiveConcurrency;
+
+        // The bodies mention other error classes; the status decides
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response(TransportResponse::new(429, "auth: too many sql requests"));
+        transport.push_response(TransportResponse::new(504, "unauthorized sql"));
+        transport.push_response(TransportResponse::new(400, "rate limit: query timed out"));
+        transport.push_error("authentication proxy timed out");
+        let policy = QueryConcurrencyPolicy::builder()
+            .max_concurrent_queries(8)
+            .adaptive(AdaptiveConcurrency::default())
+            .build();
+        let config = D1HttpConfig::new("account", "database", "token");
+        let mut conn = D1HttpConnection::new(config)
+            .with_transport(transport)
+            .with_concurrency_policy(policy.clone());
+
+        assert!(conn.batch_execute("SELECT 1").await.is_err());
+        assert_eq!(policy.current_limit(), 4);
+        assert!(conn.batch_execute("SELECT 1").await.is_err());
+        assert_eq!(policy.current_limit(), 2);
+
+        // A SQL error and a network failure say nothing about load
+        assert!(conn.batch_execute("SELECT 1").await.is_err());
+        assert!(conn.batch_execute("SELECT 1").await.is_err());
+        assert_eq!(policy.current_limit(), 2);
+        assert_eq!(policy.stats().decreases, 2);
+    }
+
     #[tokio::test]
     async fn test_establish_url_with_empty_fields() {
         // Empty account_id
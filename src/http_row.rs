@@ -3,33 +3,39 @@
 //! This module provides the D1Row type for iterating over query results
 //! when using the HTTP REST API.
 
-use std::{
-    cell::{Ref, RefCell},
-    rc::Rc,
-};
-
 use diesel::row::{Field, PartialRow, Row, RowIndex, RowSealed};
 use serde_json::Value as JsonValue;
 
 use crate::{backend::D1Backend, http_value::D1Value};
 
 /// A row from a D1 query result (HTTP version)
+///
+/// Values are kept in the column order of the query, which is the order
+/// Diesel decodes `Queryable` fields in.
 pub struct D1Row {
-    data: Rc<RefCell<JsonValue>>,
+    values: Vec<JsonValue>,
     field_vec: Vec<String>,
 }
 
-// SAFETY: The data is reference-counted and properly synchronized
-unsafe impl Send for D1Row {}
-unsafe impl Sync for D1Row {}
-
 impl D1Row {
-    /// Create a new row from a JSON value and field names
+    /// Create a new row from a JSON object and field names
+    ///
+    /// The row's columns are the named fields of the object, in the order
+    /// of `field_vec`.
     pub fn new(json_value: JsonValue, field_vec: Vec<String>) -> Self {
-        Self {
-            data: Rc::new(RefCell::new(json_value)),
-            field_vec,
-        }
+        let values = field_vec
+            .iter()
+            .map(|name| json_value.get(name).cloned().unwrap_or(JsonValue::Null))
+            .collect();
+        Self { values, field_vec }
+    }
+
+    /// Create a new row from column values and their names, in column order
+    ///
+    /// Unlike [`D1Row::new`], this keeps columns that share a name, such as
+    /// the `id` of both sides of a join.
+    pub fn from_values(values: Vec<JsonValue>, field_vec: Vec<String>) -> Self {
+        Self { values, field_vec }
     }
 }
 
@@ -53,10 +59,9 @@ impl<'stmt> Row<'stmt, D1Backend> for D1Row {
         Self: diesel::row::RowIndex<I>,
     {
         let index = self.idx(idx)?;
-        let name = self.field_vec.get(index)?;
         Some(D1Field {
-            name: name.to_string(),
-            row: self.data.borrow(),
+            name: self.field_vec.get(index)?,
+            value: self.values.get(index),
         })
     }
 
@@ -86,21 +91,20 @@ impl RowIndex<&str> for D1Row {
 
 /// A field from a D1 row (HTTP version)
 pub struct D1Field<'stmt> {
-    row: Ref<'stmt, JsonValue>,
-    name: String,
+    name: &'stmt str,
+    value: Option<&'stmt JsonValue>,
 }
 
 impl<'stmt> Field<'stmt, D1Backend> for D1Field<'stmt> {
     fn field_name(&self) -> Option<&str> {
-        Some(&self.name)
+        Some(self.name)
     }
 
     fn value(&self) -> Option<D1Value> {
-        if let Some(obj) = self.row.as_object() {
-            obj.get(&self.name).map(|v| D1Value::new(v.clone()))
-        } else {
-            None
-        }
+        // SQL NULL has no value, which Diesel decodes as `None`
+        self.value
+            .filter(|v| !v.is_null())
+            .map(|v| D1Value::new(v.clone()))
     }
 }
 
@@ -153,4 +157,58 @@ mod tests {
         let value = field.value().unwrap();
         assert!((value.read_number() - 1.0).abs() < f64::EPSILON);
     }
+
+    #[test]
+    fn test_d1_row_from_values_keeps_column_order() {
+        let row = D1Row::from_values(
+            vec![json!(2), json!("Bob"), json!(1)],
+            vec!["id".to_string(), "name".to_string(), "id".to_string()],
+        );
+        assert_eq!(row.field_count(), 3);
+
+        let names: Vec<_> = (0..3)
+            .map(|i| row.get(i).unwrap().field_name().unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["id", "name", "id"]);
+
+        // Columns sharing a name keep their own values
+        let value = row.get(2usize).unwrap().value().unwrap();
+        assert!((value.read_number() - 1.0).abs() < f64::EPSILON);
+        assert_eq!(row.idx("id"), Some(0));
+    }
+
+    #[test]
+    fn test_d1_row_null_field_has_no_value() {
+        let row = D1Row::from_values(
+            vec![JsonValue::Null, json!("test")],
+            vec!["email".to_string(), "name".to_string()],
+        );
+        assert!(row.get(0usize).unwrap().value().is_none());
+        assert!(row.get(1usize).unwrap().value().is_some());
+    }
+
+    #[test]
+    fn test_d1_row_null_field_decodes_as_none() {
+        use diesel::deserialize::FromSql;
+        use diesel::sql_types::{Nullable, Text};
+
+        let row = D1Row::from_values(
+            vec![JsonValue::Null, json!("test")],
+            vec!["email".to_string(), "name".to_string()],
+        );
+
+        let email = row.get(0usize).unwrap().value();
+        let decoded =
+            <Option<String> as FromSql<Nullable<Text>, D1Backend>>::from_nullable_sql(email);
+        assert_eq!(decoded.unwrap(), None);
+
+        // A NOT NULL type reports the NULL instead of decoding a default
+        let email = row.get(0usize).unwrap().value();
+        assert!(<String as FromSql<Text, D1Backend>>::from_nullable_sql(email).is_err());
+
+        let name = row.get(1usize).unwrap().value();
+        let decoded =
+            <Option<String> as FromSql<Nullable<Text>, D1Backend>>::from_nullable_sql(name);
+        assert_eq!(decoded.unwrap(), Some("test".to_string()));
+    }
 }
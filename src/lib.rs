@@ -7,6 +7,7 @@
 //!
 //! - `wasm` - Enable WASM bindings for Cloudflare Workers (requires wasm32 target)
 //! - `http` - Enable HTTP REST API backend for non-Workers environments
+//! - `mock-server` - Enable a local, SQLite-backed emulator of the D1 REST API for tests
 //!
 //! ## Concurrency & "Pooling" Semantics
 //!
@@ -58,9 +59,17 @@ mod utils;
 // New feature modules
 pub mod batch;
 pub mod cache;
+pub mod cache_store;
 pub mod concurrency;
+pub mod limits;
+#[cfg(feature = "mock-server")]
+pub mod mock_server;
+#[cfg(feature = "http")]
+pub mod rate_limit;
 pub mod replay;
 pub mod tracing_support;
+#[cfg(feature = "http")]
+pub mod transport;
 
 // WASM-specific modules
 #[cfg(feature = "wasm")]
@@ -82,17 +91,25 @@ mod http_value;
 
 // Re-exports
 pub use backend::D1Backend;
-pub use transaction_manager::D1TransactionManager;
+pub use transaction_manager::{D1TransactionManager, TransactionReadPolicy};
 
 // Concurrency and caching re-exports
-pub use cache::{StatementCache, StatementCacheConfig};
-pub use concurrency::QueryConcurrencyPolicy;
+pub use cache::{ResultCacheConfig, StatementCache, StatementCacheConfig};
+pub use cache_store::{CachedRows, MemoryResultStore, ResultCacheStore};
+pub use concurrency::{AdaptiveConcurrency, QueryConcurrencyPolicy};
+pub use limits::{D1Limits, LimitExceeded};
 
 #[cfg(feature = "http")]
 pub use concurrency::HttpTransportPolicy;
+#[cfg(feature = "http")]
+pub use rate_limit::{RateLimitBehavior, RateLimitPolicy, RateLimited};
+#[cfg(feature = "http")]
+pub use transport::{D1Transport, MemoryTransport, ReqwestTransport};
 
 // Batch operations re-exports
-pub use batch::{BatchBuilder, BatchStatement, BoundValue};
+pub use batch::{
+    BatchBuilder, BatchLoad, BatchStatement, BoundValue, GuardExpectation, GuardFailed,
+};
 
 // Tracing re-exports
 pub use tracing_support::{D1Span, ErrorClass, SpanOperation};
@@ -100,8 +117,12 @@ pub use tracing_support::{D1Span, ErrorClass, SpanOperation};
 // Replay testing re-exports
 pub use replay::{ExpectedResult, TransactionTranscript, TranscriptStatement};
 
+#[cfg(feature = "wasm")]
+pub use cache_store::WorkersCacheStore;
 #[cfg(feature = "wasm")]
 pub use wasm_connection::D1Connection;
 
 #[cfg(feature = "http")]
 pub use http_connection::{D1HttpConfig, D1HttpConnection};
+#[cfg(feature = "mock-server")]
+pub use mock_server::{D1Emulator, D1MockServer};
@@ -0,0 +1,358 @@
+//! Client-side validation of D1 platform limits
+//!
+//! D1 rejects statements and batches that exceed its platform limits, but
+//! the violation only surfaces as an opaque HTTP 400 or JS exception. The
+//! connections check every statement and batch against a [`D1Limits`]
+//! configuration before anything is sent, and report violations as a
+//! [`LimitExceeded`] error.
+//!
+//! The defaults follow Cloudflare's published D1 limits.
+//!
+//! # Example
+//!
+//! ```
+//! use diesel_d1::limits::D1Limits;
+//!
+//! let limits = D1Limits::builder()
+//!     .max_batch_statements(50)
+//!     .build();
+//!
+//! assert!(limits.check_statement("SELECT ?", 1).is_ok());
+//! assert!(limits.check_statement("SELECT ?", 101).is_err());
+//! ```
+
+use diesel::QueryResult;
+
+use crate::batch::{BatchBuilder, MAX_BOUND_PARAMETERS};
+
+/// Default maximum SQL statement length in bytes (100 KB)
+pub const DEFAULT_MAX_SQL_LENGTH: usize = 100_000;
+
+/// Default maximum number of statements in one batch
+pub const DEFAULT_MAX_BATCH_STATEMENTS: usize = 1_000;
+
+/// Default maximum request payload size in bytes (100 MB)
+pub const DEFAULT_MAX_PAYLOAD_BYTES: usize = 100 * 1024 * 1024;
+
+/// The D1 limit that a statement or batch exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// Length of a single SQL statement in bytes
+    SqlLength,
+    /// Number of bound parameters in a single statement
+    BoundParameters,
+    /// Number of statements in one batch
+    BatchStatements,
+    /// Size of the serialized request payload in bytes
+    PayloadSize,
+}
+
+impl LimitKind {
+    /// Get the string representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKind::SqlLength => "sql_length",
+            LimitKind::BoundParameters => "bound_parameters",
+            LimitKind::BatchStatements => "batch_statements",
+            LimitKind::PayloadSize => "payload_size",
+        }
+    }
+}
+
+impl std::fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Error reported when a statement or batch exceeds a D1 limit
+///
+/// Returned as a [`diesel::result::Error::QueryBuilderError`] before any
+/// request is made. Use [`LimitExceeded::from_error`] to recover it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    /// Which limit was exceeded
+    pub kind: LimitKind,
+    /// The measured value
+    pub actual: usize,
+    /// The configured maximum
+    pub max: usize,
+}
+
+impl LimitExceeded {
+    /// Recognise a limit violation in a Diesel error
+    pub fn from_error(error: &diesel::result::Error) -> Option<&Self> {
+        match error {
+            diesel::result::Error::QueryBuilderError(inner) => inner.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "D1 limit exceeded: {} is {}, maximum is {}",
+            self.kind, self.actual, self.max
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for diesel::result::Error {
+    fn from(error: LimitExceeded) -> Self {
+        diesel::result::Error::QueryBuilderError(Box::new(error))
+    }
+}
+
+/// D1 platform limits checked before a request is sent
+///
+/// # Example
+///
+/// ```
+/// use diesel_d1::limits::D1Limits;
+///
+/// let limits = D1Limits::builder()
+///     .max_sql_length(50_000)
+///     .max_payload_bytes(1024 * 1024)
+///     .build();
+/// assert_eq!(limits.max_bound_parameters(), 100);
+/// ```
+#[derive(Debug, Clone)]
+pub struct D1Limits {
+    /// Maximum SQL statement length in bytes
+    max_sql_length: usize,
+    /// Maximum bound parameters per statement
+    max_bound_parameters: usize,
+    /// Maximum statements per batch
+    max_batch_statements: usize,
+    /// Maximum request payload size in bytes
+    max_payload_bytes: usize,
+}
+
+impl Default for D1Limits {
+    fn default() -> Self {
+        Self {
+            max_sql_length: DEFAULT_MAX_SQL_LENGTH,
+            max_bound_parameters: MAX_BOUND_PARAMETERS,
+            max_batch_statements: DEFAULT_MAX_BATCH_STATEMENTS,
+            max_payload_bytes: DEFAULT_MAX_PAYLOAD_BYTES,
+        }
+    }
+}
+
+impl D1Limits {
+    /// Create limits matching Cloudflare's published defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder for configuring the limits
+    pub fn builder() -> D1LimitsBuilder {
+        D1LimitsBuilder::default()
+    }
+
+    /// Get the maximum SQL statement length in bytes
+    pub fn max_sql_length(&self) -> usize {
+        self.max_sql_length
+    }
+
+    /// Get the maximum bound parameters per statement
+    pub fn max_bound_parameters(&self) -> usize {
+        self.max_bound_parameters
+    }
+
+    /// Get the maximum statements per batch
+    pub fn max_batch_statements(&self) -> usize {
+        self.max_batch_statements
+    }
+
+    /// Get the maximum request payload size in bytes
+    pub fn max_payload_bytes(&self) -> usize {
+        self.max_payload_bytes
+    }
+
+    /// Check a single statement's SQL length and bound parameter count
+    pub fn check_statement(&self, sql: &str, bound_parameters: usize) -> QueryResult<()> {
+        check(LimitKind::SqlLength, sql.len(), self.max_sql_length)?;
+        check(
+            LimitKind::BoundParameters,
+            bound_parameters,
+            self.max_bound_parameters,
+        )
+    }
+
+    /// Check the number of statements in a batch
+    pub fn check_batch_size(&self, statements: usize) -> QueryResult<()> {
+        check(
+            LimitKind::BatchStatements,
+            statements,
+            self.max_batch_statements,
+        )
+    }
+
+    /// Check the size of a serialized request payload
+    pub fn check_payload(&self, bytes: usize) -> QueryResult<()> {
+        check(LimitKind::PayloadSize, bytes, self.max_payload_bytes)
+    }
+
+    /// Check a batch's statement count and every statement in it
+    pub fn check_batch(&self, batch: &BatchBuilder) -> QueryResult<()> {
+        self.check_batch_size(batch.len())?;
+        batch.statements().iter().try_for_each(|statement| {
+            self.check_statement(statement.sql(), statement.param_count())
+        })
+    }
+}
+
+/// Fail with [`LimitExceeded`] when `actual` is above `max`
+fn check(kind: LimitKind, actual: usize, max: usize) -> QueryResult<()> {
+    if actual > max {
+        return Err(LimitExceeded { kind, actual, max }.into());
+    }
+    Ok(())
+}
+
+/// Builder for D1Limits
+#[derive(Debug, Default)]
+pub struct D1LimitsBuilder {
+    max_sql_length: Option<usize>,
+    max_bound_parameters: Option<usize>,
+    max_batch_statements: Option<usize>,
+    max_payload_bytes: Option<usize>,
+}
+
+impl D1LimitsBuilder {
+    /// Set the maximum SQL statement length in bytes
+    pub fn max_sql_length(mut self, max: usize) -> Self {
+        self.max_sql_length = Some(max);
+        self
+    }
+
+    /// Set the maximum bound parameters per statement
+    pub fn max_bound_parameters(mut self, max: usize) -> Self {
+        self.max_bound_parameters = Some(max);
+        self
+    }
+
+    /// Set the maximum statements per batch
+    pub fn max_batch_statements(mut self, max: usize) -> Self {
+        self.max_batch_statements = Some(max);
+        self
+    }
+
+    /// Set the maximum request payload size in bytes
+    pub fn max_payload_bytes(mut self, max: usize) -> Self {
+        self.max_payload_bytes = Some(max);
+        self
+    }
+
+    /// Build the limits
+    pub fn build(self) -> D1Limits {
+        let default = D1Limits::default();
+        D1Limits {
+            max_sql_length: self.max_sql_length.unwrap_or(default.max_sql_length),
+            max_bound_parameters: self
+                .max_bound_parameters
+                .unwrap_or(default.max_bound_parameters),
+            max_batch_statements: self
+                .max_batch_statements
+                .unwrap_or(default.max_batch_statements),
+            max_payload_bytes: self.max_payload_bytes.unwrap_or(default.max_payload_bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_limits() {
+        let limits = D1Limits::default();
+        assert_eq!(limits.max_sql_length(), DEFAULT_MAX_SQL_LENGTH);
+        assert_eq!(limits.max_bound_parameters(), 100);
+        assert_eq!(limits.max_batch_statements(), DEFAULT_MAX_BATCH_STATEMENTS);
+        assert_eq!(limits.max_payload_bytes(), DEFAULT_MAX_PAYLOAD_BYTES);
+    }
+
+    #[test]
+    fn test_builder_overrides() {
+        let limits = D1Limits::builder()
+            .max_sql_length(10)
+            .max_bound_parameters(2)
+            .max_batch_statements(3)
+            .max_payload_bytes(4)
+            .build();
+        assert_eq!(limits.max_sql_length(), 10);
+        assert_eq!(limits.max_bound_parameters(), 2);
+        assert_eq!(limits.max_batch_statements(), 3);
+        assert_eq!(limits.max_payload_bytes(), 4);
+    }
+
+    #[test]
+    fn test_check_statement() {
+        let limits = D1Limits::builder().max_sql_length(10).build();
+        assert!(limits.check_statement("SELECT 1", 0).is_ok());
+
+        let error = limits.check_statement("SELECT 1 + 1", 0).unwrap_err();
+        assert_eq!(
+            LimitExceeded::from_error(&error),
+            Some(&LimitExceeded {
+                kind: LimitKind::SqlLength,
+                actual: 12,
+                max: 10,
+            })
+        );
+
+        let error = limits.check_statement("SELECT ?", 101).unwrap_err();
+        assert_eq!(
+            LimitExceeded::from_error(&error).map(|e| e.kind),
+            Some(LimitKind::BoundParameters)
+        );
+    }
+
+    #[test]
+    fn test_check_batch() {
+        let limits = D1Limits::builder().max_batch_statements(2).build();
+
+        let mut batch = BatchBuilder::new();
+        batch.add_raw("SELECT 1");
+        batch.add_raw("SELECT 2");
+        assert!(limits.check_batch(&batch).is_ok());
+
+        batch.add_raw("SELECT 3");
+        let error = limits.check_batch(&batch).unwrap_err();
+        assert_eq!(
+            LimitExceeded::from_error(&error).map(|e| e.kind),
+            Some(LimitKind::BatchStatements)
+        );
+    }
+
+    #[test]
+    fn test_check_payload() {
+        let limits = D1Limits::builder().max_payload_bytes(1024).build();
+        assert!(limits.check_payload(1024).is_ok());
+        assert!(limits.check_payload(1025).is_err());
+    }
+
+    #[test]
+    fn test_limit_exceeded_display() {
+        let error = LimitExceeded {
+            kind: LimitKind::BoundParameters,
+            actual: 120,
+            max: 100,
+        };
+        assert_eq!(
+            error.to_string(),
+            "D1 limit exceeded: bound_parameters is 120, maximum is 100"
+        );
+    }
+
+    #[test]
+    fn test_from_error_ignores_other_errors() {
+        assert!(LimitExceeded::from_error(&diesel::result::Error::NotFound).is_none());
+    }
+}
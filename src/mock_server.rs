@@ -0,0 +1,889 @@
+//! Local emulator of the D1 REST API, backed by SQLite
+//!
+//! [`D1MockServer`] serves the `/query` and `/raw` endpoints of the D1 REST
+//! API on a local port and runs the statements it receives against an
+//! embedded, in-memory SQLite database. Responses use D1's envelope of
+//! `success`, `errors` and `result`, with a `meta` object per statement, so a
+//! [`D1HttpConnection`](crate::D1HttpConnection) pointed at the server runs
+//! the whole Diesel DSL without a Cloudflare account.
+//!
+//! [`D1Emulator`] is the SQLite side on its own. It also implements
+//! [`D1Transport`], for tests that should not open a socket.
+//!
+//! The emulator accepts any account ID, database ID and non-empty API token.
+//! Every request is applied atomically, like D1 does, but `meta.rows_read`
+//! counts the rows returned rather than the rows scanned.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use diesel_async::{RunQueryDsl, SimpleAsyncConnection};
+//! use diesel_d1::{D1HttpConnection, D1MockServer};
+//!
+//! let server = D1MockServer::start().await?;
+//! let mut conn = D1HttpConnection::new(server.config());
+//! conn.batch_execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL)")
+//!     .await?;
+//!
+//! let names: Vec<String> = users::table.select(users::name).load(&mut conn).await?;
+//! ```
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Batch, Connection, ErrorCode, Statement};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::http_connection::D1HttpConfig;
+use crate::transport::{D1Transport, TransportRequest, TransportResponse, TransportResult};
+
+/// D1 error code for a failed SQL statement
+const SQL_ERROR_CODE: u32 = 7500;
+/// D1 error code for a request body that could not be parsed
+const MALFORMED_REQUEST_CODE: u32 = 7400;
+/// Cloudflare API error code for a path that matches no endpoint
+const UNKNOWN_ROUTE_CODE: u32 = 7003;
+/// Cloudflare API error code for a missing API token
+const AUTHENTICATION_ERROR_CODE: u32 = 10000;
+
+/// Endpoints of the D1 REST API served by the emulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    /// `/query`, which returns rows as objects keyed by column name
+    Query,
+    /// `/raw`, which returns column names plus rows of values
+    Raw,
+}
+
+/// Body of a request to a query endpoint
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum QueryBody {
+    /// Several statements, applied in one transaction
+    Batch { batch: Vec<Query> },
+    /// One SQL string, which may hold several `;`-separated statements
+    Single(Query),
+}
+
+impl QueryBody {
+    fn into_queries(self) -> Vec<Query> {
+        match self {
+            QueryBody::Batch { batch } => batch,
+            QueryBody::Single(query) => vec![query],
+        }
+    }
+}
+
+/// SQL and bound parameters of one query
+#[derive(Deserialize, Debug)]
+struct Query {
+    sql: String,
+    #[serde(default)]
+    params: Vec<JsonValue>,
+}
+
+/// SQLite-backed implementation of the D1 query endpoints
+///
+/// Answers requests the way the D1 REST API does, from an in-memory
+/// database that lives as long as the emulator.
+///
+/// # Example
+///
+/// ```
+/// use diesel_d1::mock_server::D1Emulator;
+/// use diesel_d1::transport::TransportRequest;
+///
+/// let emulator = D1Emulator::new().unwrap();
+/// let response = emulator.handle(&TransportRequest {
+///     url: "http://localhost/accounts/a/d1/database/d/raw".to_string(),
+///     headers: vec![("Authorization".to_string(), "Bearer token".to_string())],
+///     body: br#"{"sql":"SELECT 1 AS one"}"#.to_vec(),
+/// });
+/// assert_eq!(response.status, 200);
+/// ```
+pub struct D1Emulator {
+    connection: Mutex<Connection>,
+}
+
+impl D1Emulator {
+    /// Create an emulator with an empty database
+    pub fn new() -> rusqlite::Result<Self> {
+        Ok(Self {
+            connection: Mutex::new(Connection::open_in_memory()?),
+        })
+    }
+
+    /// Answer a request to the D1 REST API
+    ///
+    /// `request.url` may be a full URL or just its path. Failed statements
+    /// are answered with status 400, like D1 does, and undo every other
+    /// statement of the request.
+    pub fn handle(&self, request: &TransportRequest) -> TransportResponse {
+        let Some(endpoint) = route(&request.url) else {
+            return error_response(
+                404,
+                UNKNOWN_ROUTE_CODE,
+                format!(
+                    "Could not route to {}, perhaps your object identifier is invalid?",
+                    request.url
+                ),
+            );
+        };
+
+        let authorized = request
+            .header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| !token.trim().is_empty());
+        if !authorized {
+            return error_response(401, AUTHENTICATION_ERROR_CODE, "Authentication error");
+        }
+
+        let body: QueryBody = match serde_json::from_slice(&request.body) {
+            Ok(body) => body,
+            Err(e) => {
+                return error_response(
+                    400,
+                    MALFORMED_REQUEST_CODE,
+                    format!("The request is malformed: {}", e),
+                )
+            }
+        };
+
+        match self.execute(&body.into_queries(), endpoint) {
+            Ok(results) => json_response(
+                200,
+                json!({
+                    "result": results,
+                    "success": true,
+                    "errors": [],
+                    "messages": [],
+                }),
+            ),
+            Err(message) => error_response(400, SQL_ERROR_CODE, message),
+        }
+    }
+
+    /// Run every statement of a request in one transaction
+    fn execute(&self, queries: &[Query], endpoint: Endpoint) -> Result<Vec<JsonValue>, String> {
+        let conn = self
+            .connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        conn.execute_batch("SAVEPOINT d1_request")
+            .map_err(sql_error)?;
+
+        let mut results = Vec::new();
+        let outcome = queries
+            .iter()
+            .try_for_each(|query| run_query(&conn, query, endpoint, &mut results));
+
+        let finish = if outcome.is_ok() {
+            "RELEASE d1_request"
+        } else {
+            "ROLLBACK TO d1_request; RELEASE d1_request"
+        };
+        let finished = conn.execute_batch(finish).map_err(sql_error);
+
+        outcome?;
+        finished?;
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl D1Transport for D1Emulator {
+    async fn send(&self, request: TransportRequest) -> TransportResult<TransportResponse> {
+        Ok(self.handle(&request))
+    }
+}
+
+/// Run every statement of `query`, adding their result sets to `results`
+fn run_query(
+    conn: &Connection,
+    query: &Query,
+    endpoint: Endpoint,
+    results: &mut Vec<JsonValue>,
+) -> Result<(), String> {
+    let mut statements = Batch::new(conn, &query.sql);
+    while let Some(mut statement) = statements.next().map_err(sql_error)? {
+        let result =
+            run_statement(conn, &mut statement, &query.params, endpoint).map_err(sql_error)?;
+        results.push(result);
+    }
+    Ok(())
+}
+
+/// Run one statement and build its result set, as D1 reports it
+///
+/// `params` are bound to statements that take parameters; statements
+/// without any ignore them.
+fn run_statement(
+    conn: &Connection,
+    statement: &mut Statement<'_>,
+    params: &[JsonValue],
+    endpoint: Endpoint,
+) -> rusqlite::Result<JsonValue> {
+    let started = Instant::now();
+
+    let expected = statement.parameter_count();
+    if expected > 0 {
+        if params.len() != expected {
+            return Err(rusqlite::Error::InvalidParameterCount(
+                params.len(),
+                expected,
+            ));
+        }
+        for (index, param) in params.iter().enumerate() {
+            statement.raw_bind_parameter(index + 1, bind_value(param))?;
+        }
+    }
+
+    let read_only = statement.readonly();
+    let columns: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let changes_before = conn.total_changes();
+
+    let mut rows = Vec::new();
+    let mut cursor = statement.raw_query();
+    while let Some(row) = cursor.next()? {
+        let values = (0..columns.len())
+            .map(|index| row.get_ref(index).map(column_value))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.push(values);
+    }
+
+    let changes = conn.total_changes() - changes_before;
+    let meta = json!({
+        "served_by": "diesel-d1-mock-server",
+        "duration": started.elapsed().as_secs_f64() * 1000.0,
+        "changes": changes,
+        "last_row_id": conn.last_insert_rowid(),
+        "changed_db": !read_only,
+        "rows_read": rows.len(),
+        "rows_written": changes,
+    });
+
+    let results = match endpoint {
+        Endpoint::Raw => json!({ "columns": columns, "rows": rows }),
+        Endpoint::Query => rows
+            .into_iter()
+            .map(|values| JsonValue::Object(columns.iter().cloned().zip(values).collect()))
+            .collect(),
+    };
+
+    Ok(json!({
+        "results": results,
+        "success": true,
+        "meta": meta,
+    }))
+}
+
+/// Find the endpoint a URL or path addresses
+fn route(url: &str) -> Option<Endpoint> {
+    // Drop the scheme and host of a full URL, and any query string
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => url,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        [.., "accounts", _, "d1", "database", _, "query"] => Some(Endpoint::Query),
+        [.., "accounts", _, "d1", "database", _, "raw"] => Some(Endpoint::Raw),
+        _ => None,
+    }
+}
+
+/// Convert a JSON parameter into the SQLite value it binds
+///
+/// Blobs arrive base64-encoded and are stored as text, which reads back as
+/// the same bytes.
+fn bind_value(value: &JsonValue) -> Value {
+    match value {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Integer(i64::from(*b)),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(s) => Value::Text(s.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+/// Convert a SQLite column value into JSON, as D1 returns it
+fn column_value(value: ValueRef<'_>) -> JsonValue {
+    match value {
+        ValueRef::Null => JsonValue::Null,
+        ValueRef::Integer(i) => JsonValue::from(i),
+        ValueRef::Real(f) => JsonValue::from(f),
+        ValueRef::Text(text) => JsonValue::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => bytes.iter().copied().map(JsonValue::from).collect(),
+    }
+}
+
+/// Describe a SQLite error the way D1 does, with the result code appended
+fn sql_error(error: rusqlite::Error) -> String {
+    let code = match error.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => "SQLITE_CONSTRAINT",
+        Some(ErrorCode::TooBig) => "SQLITE_TOOBIG",
+        _ => "SQLITE_ERROR",
+    };
+    format!("{}: {}", error, code)
+}
+
+/// Build a JSON response
+fn json_response(status: u16, body: JsonValue) -> TransportResponse {
+    TransportResponse::new(status, body.to_string()).with_header("Content-Type", "application/json")
+}
+
+/// Build a failed response in the envelope of the Cloudflare API
+fn error_response(status: u16, code: u32, message: impl Into<String>) -> TransportResponse {
+    json_response(
+        status,
+        json!({
+            "result": [],
+            "success": false,
+            "errors": [{ "code": code, "message": message.into() }],
+            "messages": [],
+        }),
+    )
+}
+
+/// HTTP server on a local port that emulates the D1 REST API
+///
+/// Requests are answered by a [`D1Emulator`], so every connection to the
+/// server shares one database. The server stops when dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let server = D1MockServer::start().await?;
+/// let mut conn = D1HttpConnection::new(server.config());
+/// ```
+pub struct D1MockServer {
+    addr: SocketAddr,
+    emulator: Arc<D1Emulator>,
+    task: JoinHandle<()>,
+}
+
+impl D1MockServer {
+    /// Start a server with an empty database on a free port of `127.0.0.1`
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub async fn start() -> io::Result<Self> {
+        let emulator = Arc::new(D1Emulator::new().map_err(io::Error::other)?);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(serve(listener, emulator.clone()));
+
+        Ok(Self {
+            addr,
+            emulator,
+            task,
+        })
+    }
+
+    /// Get the address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Get the base URL of the API, to pass to [`D1HttpConfig::with_base_url`]
+    pub fn base_url(&self) -> String {
+        format!("http://{}/client/v4", self.addr)
+    }
+
+    /// Get a configuration for connecting to the server
+    pub fn config(&self) -> D1HttpConfig {
+        D1HttpConfig::new("mock-account", "mock-database", "mock-token")
+            .with_base_url(self.base_url())
+    }
+
+    /// Get the emulator answering the server's requests
+    pub fn emulator(&self) -> &D1Emulator {
+        &self.emulator
+    }
+}
+
+impl Drop for D1MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Accept connections until the server task is aborted
+async fn serve(listener: TcpListener, emulator: Arc<D1Emulator>) {
+    loop {
+        let Ok((socket, _)) = listener.accept().await else {
+            continue;
+        };
+        let emulator = emulator.clone();
+        tokio::spawn(async move {
+            // A client that hangs up mid-request needs no answer
+            let _ = serve_connection(socket, &emulator).await;
+        });
+    }
+}
+
+/// Answer one HTTP/1.1 request, then close the connection
+async fn serve_connection(mut socket: TcpStream, emulator: &D1Emulator) -> io::Result<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+
+    let head_len = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_len]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let mut request = TransportRequest {
+        url: path.to_string(),
+        headers,
+        body: buffer.split_off(head_len),
+    };
+
+    let content_length = request
+        .header("Content-Length")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    while request.body.len() < content_length {
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.body.extend_from_slice(&chunk[..read]);
+    }
+    request.body.truncate(content_length);
+
+    let response = if method == "POST" {
+        emulator.handle(&request)
+    } else {
+        error_response(405, MALFORMED_REQUEST_CODE, "Method not allowed")
+    };
+
+    let reason = StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Unknown");
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(&response.body).await?;
+    socket.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::D1HttpConnection;
+    use diesel::prelude::*;
+    use diesel_async::scoped_futures::ScopedFutureExt;
+    use diesel_async::{AsyncConnection, RunQueryDsl, SimpleAsyncConnection};
+
+    diesel::table! {
+        users (id) {
+            id -> Integer,
+            name -> Text,
+            email -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        posts (id) {
+            id -> Integer,
+            user_id -> Integer,
+            title -> Text,
+        }
+    }
+
+    diesel::joinable!(posts -> users (user_id));
+    diesel::allow_tables_to_appear_in_same_query!(users, posts);
+
+    const SCHEMA: &str = "
+        CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, email TEXT);
+        CREATE TABLE posts (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL, title TEXT NOT NULL);
+    ";
+
+    fn request(endpoint: &str, body: JsonValue) -> TransportRequest {
+        TransportRequest {
+            url: format!(
+                "http://d1.test/client/v4/accounts/a/d1/database/d/{}",
+                endpoint
+            ),
+            headers: vec![("Authorization".to_string(), "Bearer token".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn response_json(response: &TransportResponse) -> JsonValue {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    async fn connect(server: &D1MockServer) -> D1HttpConnection {
+        let mut conn = D1HttpConnection::new(server.config());
+        conn.batch_execute(SCHEMA).await.unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_route_matches_query_endpoints() {
+        assert_eq!(
+            route("https://api.cloudflare.com/client/v4/accounts/a/d1/database/d/query"),
+            Some(Endpoint::Query)
+        );
+        assert_eq!(
+            route("/accounts/a/d1/database/d/raw?trace=1"),
+            Some(Endpoint::Raw)
+        );
+        assert_eq!(route("/accounts/a/d1/database/d/export"), None);
+        assert_eq!(route("/accounts/a/d1/database"), None);
+        assert_eq!(route("http://127.0.0.1:8080"), None);
+    }
+
+    #[test]
+    fn test_query_endpoint_returns_d1_envelope() {
+        let emulator = D1Emulator::new().unwrap();
+        emulator.handle(&request("query", json!({ "sql": SCHEMA })));
+
+        let response = emulator.handle(&request(
+            "query",
+            json!({
+                "sql": "INSERT INTO users (name, email) VALUES (?, ?)",
+                "params": ["Alice", null],
+            }),
+        ));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        let body = response_json(&response);
+        assert_eq!(body["success"], true);
+        assert_eq!(body["errors"], json!([]));
+        let meta = &body["result"][0]["meta"];
+        assert_eq!(meta["changes"], 1);
+        assert_eq!(meta["last_row_id"], 1);
+        assert_eq!(meta["changed_db"], true);
+
+        let response = emulator.handle(&request(
+            "query",
+            json!({ "sql": "SELECT id, name, email FROM users" }),
+        ));
+        let body = response_json(&response);
+        assert_eq!(
+            body["result"][0]["results"],
+            json!([{ "id": 1, "name": "Alice", "email": null }])
+        );
+        assert_eq!(body["result"][0]["meta"]["rows_read"], 1);
+        assert_eq!(body["result"][0]["meta"]["changed_db"], false);
+    }
+
+    #[test]
+    fn test_raw_endpoint_keeps_duplicate_columns() {
+        let emulator = D1Emulator::new().unwrap();
+        let response = emulator.handle(&request(
+            "raw",
+            json!({ "sql": "SELECT 1 AS id, 'x' AS name, 2 AS id, X'0102' AS data, 1.5 AS score" }),
+        ));
+
+        let results = &response_json(&response)["result"][0]["results"];
+        assert_eq!(
+            results["columns"],
+            json!(["id", "name", "id", "data", "score"])
+        );
+        assert_eq!(results["rows"], json!([[1, "x", 2, [1, 2], 1.5]]));
+    }
+
+    #[test]
+    fn test_multi_statement_sql_returns_one_result_each() {
+        let emulator = D1Emulator::new().unwrap();
+        let response = emulator.handle(&request(
+            "raw",
+            json!({ "sql": "CREATE TABLE t (v INTEGER); INSERT INTO t VALUES (1), (2); SELECT v FROM t;" }),
+        ));
+
+        let result = &response_json(&response)["result"];
+        assert_eq!(result.as_array().unwrap().len(), 3);
+        assert_eq!(result[1]["meta"]["changes"], 2);
+        assert_eq!(result[2]["results"]["rows"], json!([[1], [2]]));
+    }
+
+    #[test]
+    fn test_failed_batch_is_rolled_back() {
+        let emulator = D1Emulator::new().unwrap();
+        emulator.handle(&request("query", json!({ "sql": SCHEMA })));
+
+        let response = emulator.handle(&request(
+            "query",
+            json!({ "batch": [
+                { "sql": "INSERT INTO users (name) VALUES (?)", "params": ["Alice"] },
+                { "sql": "INSERT INTO missing (name) VALUES (?)", "params": ["Bob"] },
+            ]}),
+        ));
+        assert_eq!(response.status, 400);
+        let body = response_json(&response);
+        assert_eq!(body["success"], false);
+        assert_eq!(body["errors"][0]["code"], SQL_ERROR_CODE);
+        assert_eq!(
+            body["errors"][0]["message"],
+            "no such table: missing: SQLITE_ERROR"
+        );
+
+        let response = emulator.handle(&request(
+            "raw",
+            json!({ "sql": "SELECT COUNT(*) FROM users" }),
+        ));
+        assert_eq!(
+            response_json(&response)["result"][0]["results"]["rows"],
+            json!([[0]])
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_requests() {
+        let emulator = D1Emulator::new().unwrap();
+
+        let mut unauthorized = request("query", json!({ "sql": "SELECT 1" }));
+        unauthorized.headers.clear();
+        let response = emulator.handle(&unauthorized);
+        assert_eq!(response.status, 401);
+        assert_eq!(
+            response_json(&response)["errors"][0]["code"],
+            AUTHENTICATION_ERROR_CODE
+        );
+
+        let response = emulator.handle(&request("export", json!({ "sql": "SELECT 1" })));
+        assert_eq!(response.status, 404);
+
+        let response = emulator.handle(&request("query", json!({ "query": "SELECT 1" })));
+        assert_eq!(response.status, 400);
+        assert_eq!(
+            response_json(&response)["errors"][0]["code"],
+            MALFORMED_REQUEST_CODE
+        );
+
+        let response = emulator.handle(&request(
+            "query",
+            json!({ "sql": "SELECT ?, ?", "params": [1] }),
+        ));
+        assert_eq!(response.status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_diesel_dsl_end_to_end() {
+        let server = D1MockServer::start().await.unwrap();
+        let mut conn = connect(&server).await;
+
+        diesel::insert_into(users::table)
+            .values((
+                users::name.eq("Alice"),
+                users::email.eq("alice@example.com"),
+            ))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let inserted = diesel::insert_into(users::table)
+            .values(vec![users::name.eq("Bob"), users::name.eq("Carol")])
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(inserted, 2);
+
+        let loaded: Vec<(i32, String, Option<String>)> =
+            users::table.order(users::id).load(&mut conn).await.unwrap();
+        assert_eq!(
+            loaded,
+            vec![
+                (
+                    1,
+                    "Alice".to_string(),
+                    Some("alice@example.com".to_string())
+                ),
+                (2, "Bob".to_string(), None),
+                (3, "Carol".to_string(), None),
+            ]
+        );
+
+        let updated = diesel::update(users::table.filter(users::email.is_null()))
+            .set(users::email.eq("unknown"))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(updated, 2);
+
+        diesel::delete(users::table.filter(users::name.eq("Bob")))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let names: Vec<String> = users::table
+            .filter(users::email.eq("unknown"))
+            .select(users::name)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(names, vec!["Carol"]);
+
+        let count: i64 = users::table.count().get_result(&mut conn).await.unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_join_keeps_columns_that_share_a_name() {
+        let server = D1MockServer::start().await.unwrap();
+        let mut conn = connect(&server).await;
+
+        conn.batch_execute(
+            "INSERT INTO users (id, name) VALUES (7, 'Alice');
+             INSERT INTO posts (id, user_id, title) VALUES (1, 7, 'Hello');",
+        )
+        .await
+        .unwrap();
+
+        let rows: Vec<(i32, i32, String)> = users::table
+            .inner_join(posts::table)
+            .select((users::id, posts::id, posts::title))
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![(7, 1, "Hello".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_transactions_commit_and_roll_back() {
+        let server = D1MockServer::start().await.unwrap();
+        let mut conn = connect(&server).await;
+
+        conn.transaction(|conn| {
+            async move {
+                diesel::insert_into(users::table)
+                    .values(users::name.eq("Alice"))
+                    .execute(conn)
+                    .await?;
+                diesel::insert_into(posts::table)
+                    .values((posts::user_id.eq(1), posts::title.eq("First")))
+                    .execute(conn)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+        .unwrap();
+
+        let result: QueryResult<()> = conn
+            .transaction(|conn| {
+                async move {
+                    diesel::insert_into(users::table)
+                        .values(users::name.eq("Bob"))
+                        .execute(conn)
+                        .await?;
+                    Err(diesel::result::Error::RollbackTransaction)
+                }
+                .scope_boxed()
+            })
+            .await;
+        assert!(result.is_err());
+
+        let names: Vec<String> = users::table
+            .select(users::name)
+            .load(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(names, vec!["Alice"]);
+        let posts: i64 = posts::table.count().get_result(&mut conn).await.unwrap();
+        assert_eq!(posts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sql_errors_reach_the_connection() {
+        let server = D1MockServer::start().await.unwrap();
+        let mut conn = connect(&server).await;
+
+        diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("Alice")))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let error = diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("Alice again")))
+            .execute(&mut conn)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("UNIQUE constraint failed"));
+
+        let result = conn
+            .execute_script("INSERT INTO users (name) VALUES ('Bob'); SELECT * FROM missing;")
+            .await;
+        assert!(result.is_err());
+        let count: i64 = users::table.count().get_result(&mut conn).await.unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_emulator_as_transport() {
+        let emulator = Arc::new(D1Emulator::new().unwrap());
+        let mut conn = D1HttpConnection::new(D1HttpConfig::new("account", "database", "token"))
+            .with_transport(emulator.clone());
+        conn.batch_execute(SCHEMA).await.unwrap();
+
+        diesel::insert_into(users::table)
+            .values(users::name.eq("Alice"))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let response = emulator.handle(&request("raw", json!({ "sql": "SELECT name FROM users" })));
+        assert_eq!(
+            response_json(&response)["result"][0]["results"]["rows"],
+            json!([["Alice"]])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_other_methods() {
+        let server = D1MockServer::start().await.unwrap();
+        let mut socket = TcpStream::connect(server.addr()).await.unwrap();
+        socket
+            .write_all(b"GET /client/v4/accounts/a/d1/database/d/query HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
@@ -0,0 +1,43 @@
+use diesel::insertable::CanInsertInSingleQuery;
+use diesel::query_builder::{AstPass, BatchInsert, QueryFragment, ValuesClause};
+use diesel::result::QueryResult;
+
+use crate::backend::{D1Backend, SqliteBatchInsert};
+
+// Multi-row inserts are written as a single `INSERT ... VALUES (...), (...)`
+// statement, which SQLite accepts as long as every row sets the same columns.
+// D1 caps the number of bound parameters per statement, see
+// `BatchBuilder::push_chunked_insert` for splitting large inserts.
+impl<V, Tab, QId, const STATIC_QUERY_ID: bool> QueryFragment<D1Backend, SqliteBatchInsert>
+    for BatchInsert<Vec<ValuesClause<V, Tab>>, Tab, QId, STATIC_QUERY_ID>
+where
+    ValuesClause<V, Tab>: QueryFragment<D1Backend>,
+    V: QueryFragment<D1Backend>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, D1Backend>) -> QueryResult<()> {
+        if !STATIC_QUERY_ID {
+            out.unsafe_to_cache_prepared();
+        }
+
+        let mut values = self.values.iter();
+        if let Some(value) = values.next() {
+            value.walk_ast(out.reborrow())?;
+        }
+        for value in values {
+            out.push_sql(", (");
+            value.values.walk_ast(out.reborrow())?;
+            out.push_sql(")");
+        }
+        Ok(())
+    }
+}
+
+// Diesel only counts the rows of a batch insert for backends with a `DEFAULT`
+// keyword, so D1 provides its own count: one row per values clause.
+impl<V, Tab, QId, const STATIC_QUERY_ID: bool> CanInsertInSingleQuery<D1Backend>
+    for BatchInsert<Vec<ValuesClause<V, Tab>>, Tab, QId, STATIC_QUERY_ID>
+{
+    fn rows_to_insert(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
@@ -7,6 +7,7 @@ use super::backend::D1Backend;
 use diesel::query_builder::QueryBuilder;
 use diesel::result::QueryResult;
 
+mod batch_insert;
 mod limit_offset;
 mod returning;
 
@@ -0,0 +1,484 @@
+//! Client-side pacing of D1 REST API requests
+//!
+//! The Cloudflare API rate-limits requests per account, and a client that
+//! exceeds its budget gets HTTP 429 responses until the window rolls over.
+//! A [`RateLimitPolicy`] is a token bucket sized to that budget: it allows a
+//! burst of up to the full budget and then refills evenly across the window.
+//! Requests that don't fit either wait for their turn or fail fast with a
+//! [`RateLimited`] error, depending on the configured [`RateLimitBehavior`].
+//!
+//! Clones of a policy share one bucket, so give every connection that uses
+//! the same account a clone of the same policy.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use diesel_d1::rate_limit::{RateLimitBehavior, RateLimitPolicy};
+//!
+//! let policy = RateLimitPolicy::builder()
+//!     .requests_per_window(100)
+//!     .window(Duration::from_secs(60))
+//!     .behavior(RateLimitBehavior::FailFast)
+//!     .build();
+//!
+//! assert!(policy.try_acquire().is_ok());
+//! assert_eq!(policy.available(), 99);
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Default request budget per window (the Cloudflare API allows 1,200
+/// requests per five minutes)
+pub const DEFAULT_RATE_LIMIT_REQUESTS: u32 = 1_200;
+
+/// Default length of the rate limit window
+pub const DEFAULT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(300);
+
+/// What a request does when the budget is exhausted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitBehavior {
+    /// Wait until the request fits in the budget; requests are served in
+    /// the order they arrive
+    Queue,
+    /// Fail immediately with a [`RateLimited`] error
+    FailFast,
+}
+
+/// Error reported when a request does not fit in the rate limit budget
+///
+/// Returned as a [`diesel::result::Error::QueryBuilderError`] before any
+/// request is made. Use [`RateLimited::from_error`] to recover it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    /// How long until the request would fit in the budget
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// Recognise a rate limit rejection in a Diesel error
+    pub fn from_error(error: &diesel::result::Error) -> Option<&Self> {
+        match error {
+            diesel::result::Error::QueryBuilderError(inner) => inner.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "D1 API rate limit reached, retry after {:?}",
+            self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+impl From<RateLimited> for diesel::result::Error {
+    fn from(error: RateLimited) -> Self {
+        diesel::result::Error::QueryBuilderError(Box::new(error))
+    }
+}
+
+/// Token bucket pacing requests against a requests-per-window budget
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use diesel_d1::rate_limit::RateLimitPolicy;
+///
+/// let policy = RateLimitPolicy::new(2, Duration::from_secs(60));
+/// assert!(policy.try_acquire().is_ok());
+/// assert!(policy.try_acquire().is_ok());
+/// assert!(policy.try_acquire().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    /// Requests allowed per window, which is also the burst size
+    requests_per_window: u32,
+    /// Length of the window
+    window: Duration,
+    /// What to do when the budget is exhausted
+    behavior: RateLimitBehavior,
+    /// Longest a queued request may wait before failing instead
+    max_wait: Option<Duration>,
+    /// Bucket shared across clones
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_RATE_LIMIT_REQUESTS, DEFAULT_RATE_LIMIT_WINDOW)
+    }
+}
+
+impl RateLimitPolicy {
+    /// Create a queueing policy allowing `requests_per_window` requests per
+    /// `window`
+    ///
+    /// The budget is at least one request and the window at least one
+    /// millisecond.
+    pub fn new(requests_per_window: u32, window: Duration) -> Self {
+        Self::with_behavior(requests_per_window, window, RateLimitBehavior::Queue, None)
+    }
+
+    /// Create a builder for configuring the policy
+    pub fn builder() -> RateLimitPolicyBuilder {
+        RateLimitPolicyBuilder::default()
+    }
+
+    fn with_behavior(
+        requests_per_window: u32,
+        window: Duration,
+        behavior: RateLimitBehavior,
+        max_wait: Option<Duration>,
+    ) -> Self {
+        let requests_per_window = requests_per_window.max(1);
+        let window = window.max(Duration::from_millis(1));
+        Self {
+            requests_per_window,
+            window,
+            behavior,
+            max_wait,
+            bucket: Arc::new(Mutex::new(TokenBucket::new(
+                requests_per_window,
+                window,
+                Instant::now(),
+            ))),
+        }
+    }
+
+    /// Get the number of requests allowed per window
+    pub fn requests_per_window(&self) -> u32 {
+        self.requests_per_window
+    }
+
+    /// Get the length of the window
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Get what a request does when the budget is exhausted
+    pub fn behavior(&self) -> RateLimitBehavior {
+        self.behavior
+    }
+
+    /// Get the longest a queued request may wait, if limited
+    pub fn max_wait(&self) -> Option<Duration> {
+        self.max_wait
+    }
+
+    /// Get the number of requests that can be sent right now without waiting
+    pub fn available(&self) -> u32 {
+        self.lock().available(Instant::now())
+    }
+
+    /// Take one request from the budget without waiting
+    ///
+    /// Returns [`RateLimited`] with the time until a request fits if the
+    /// budget is exhausted, whatever the configured behavior.
+    pub fn try_acquire(&self) -> Result<(), RateLimited> {
+        self.lock().try_take(Instant::now())
+    }
+
+    /// Take one request from the budget, waiting if the behavior allows it
+    ///
+    /// With [`RateLimitBehavior::Queue`] the request reserves the next slot
+    /// and sleeps until it comes up, unless that is further away than
+    /// `max_wait`. With [`RateLimitBehavior::FailFast`] this is the same as
+    /// [`try_acquire`](Self::try_acquire).
+    ///
+    /// Dropping the future while it waits gives the reserved slot back.
+    pub async fn acquire(&self) -> Result<(), RateLimited> {
+        if self.behavior == RateLimitBehavior::FailFast {
+            return self.try_acquire();
+        }
+
+        let delay = self.lock().reserve(Instant::now(), self.max_wait)?;
+        if !delay.is_zero() {
+            let reservation = Reservation {
+                bucket: &self.bucket,
+            };
+            tokio::time::sleep(delay).await;
+            // The slot is used, so there is nothing to give back
+            std::mem::forget(reservation);
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TokenBucket> {
+        // The bucket is never left half-updated, so a poisoned lock is still usable
+        self.bucket.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Gives a reserved slot back if the waiting request is cancelled
+struct Reservation<'a> {
+    bucket: &'a Mutex<TokenBucket>,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.bucket
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .refund();
+    }
+}
+
+/// Token count and refill bookkeeping
+///
+/// Tokens go negative while requests are queued; each queued request owns
+/// one of the missing tokens and waits for it to be refilled.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    /// Tokens refilled per second
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_window: u32, window: Duration, now: Instant) -> Self {
+        let capacity = f64::from(requests_per_window);
+        Self {
+            capacity,
+            rate: capacity / window.as_secs_f64(),
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Time until the token count reaches `tokens`
+    fn time_until(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(((tokens - self.tokens) / self.rate).max(0.0))
+    }
+
+    fn available(&mut self, now: Instant) -> u32 {
+        self.refill(now);
+        self.tokens.max(0.0) as u32
+    }
+
+    fn try_take(&mut self, now: Instant) -> Result<(), RateLimited> {
+        self.refill(now);
+        if self.tokens < 1.0 {
+            return Err(RateLimited {
+                retry_after: self.time_until(1.0),
+            });
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Reserve the next slot and return how long to wait for it
+    fn reserve(
+        &mut self,
+        now: Instant,
+        max_wait: Option<Duration>,
+    ) -> Result<Duration, RateLimited> {
+        self.refill(now);
+        let delay = self.time_until(1.0);
+        if max_wait.is_some_and(|max_wait| delay > max_wait) {
+            return Err(RateLimited { retry_after: delay });
+        }
+        self.tokens -= 1.0;
+        Ok(delay)
+    }
+
+    fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.capacity);
+    }
+}
+
+/// Builder for RateLimitPolicy
+#[derive(Debug, Default)]
+pub struct RateLimitPolicyBuilder {
+    requests_per_window: Option<u32>,
+    window: Option<Duration>,
+    behavior: Option<RateLimitBehavior>,
+    max_wait: Option<Duration>,
+}
+
+impl RateLimitPolicyBuilder {
+    /// Set the number of requests allowed per window
+    pub fn requests_per_window(mut self, requests: u32) -> Self {
+        self.requests_per_window = Some(requests);
+        self
+    }
+
+    /// Set the length of the window
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// Set what a request does when the budget is exhausted
+    pub fn behavior(mut self, behavior: RateLimitBehavior) -> Self {
+        self.behavior = Some(behavior);
+        self
+    }
+
+    /// Set the longest a queued request may wait before failing instead
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    /// Build the policy
+    pub fn build(self) -> RateLimitPolicy {
+        RateLimitPolicy::with_behavior(
+            self.requests_per_window
+                .unwrap_or(DEFAULT_RATE_LIMIT_REQUESTS),
+            self.window.unwrap_or(DEFAULT_RATE_LIMIT_WINDOW),
+            self.behavior.unwrap_or(RateLimitBehavior::Queue),
+            self.max_wait,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy() {
+        let policy = RateLimitPolicy::default();
+        assert_eq!(policy.requests_per_window(), DEFAULT_RATE_LIMIT_REQUESTS);
+        assert_eq!(policy.window(), DEFAULT_RATE_LIMIT_WINDOW);
+        assert_eq!(policy.behavior(), RateLimitBehavior::Queue);
+        assert_eq!(policy.max_wait(), None);
+        assert_eq!(policy.available(), DEFAULT_RATE_LIMIT_REQUESTS);
+    }
+
+    #[test]
+    fn test_bucket_allows_burst_then_refills() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, Duration::from_secs(10), start);
+
+        assert!(bucket.try_take(start).is_ok());
+        assert!(bucket.try_take(start).is_ok());
+        let err = bucket.try_take(start).unwrap_err();
+        assert_eq!(err.retry_after, Duration::from_secs(5));
+
+        // One token refills every five seconds
+        let later = start + Duration::from_secs(5);
+        assert_eq!(bucket.available(later), 1);
+        assert!(bucket.try_take(later).is_ok());
+    }
+
+    #[test]
+    fn test_bucket_never_exceeds_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(3, Duration::from_secs(3), start);
+        assert_eq!(bucket.available(start + Duration::from_secs(60)), 3);
+    }
+
+    #[test]
+    fn test_reservations_queue_in_order() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1, Duration::from_secs(2), start);
+
+        assert_eq!(bucket.reserve(start, None).unwrap(), Duration::ZERO);
+        assert_eq!(bucket.reserve(start, None).unwrap(), Duration::from_secs(2));
+        assert_eq!(bucket.reserve(start, None).unwrap(), Duration::from_secs(4));
+        assert_eq!(bucket.available(start), 0);
+    }
+
+    #[test]
+    fn test_reserve_respects_max_wait() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1, Duration::from_secs(2), start);
+        let max_wait = Some(Duration::from_secs(3));
+
+        assert!(bucket.reserve(start, max_wait).is_ok());
+        assert!(bucket.reserve(start, max_wait).is_ok());
+        let err = bucket.reserve(start, max_wait).unwrap_err();
+        assert_eq!(err.retry_after, Duration::from_secs(4));
+
+        // The rejected request did not take a slot
+        assert_eq!(bucket.reserve(start, None).unwrap(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_refund_returns_reserved_slot() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1, Duration::from_secs(2), start);
+
+        assert!(bucket.reserve(start, None).is_ok());
+        assert!(bucket.reserve(start, None).is_ok());
+        bucket.refund();
+        assert_eq!(bucket.reserve(start, None).unwrap(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_clones_share_budget() {
+        let policy = RateLimitPolicy::new(2, Duration::from_secs(60));
+        let other = policy.clone();
+
+        assert!(policy.try_acquire().is_ok());
+        assert!(other.try_acquire().is_ok());
+        assert!(policy.try_acquire().is_err());
+        assert_eq!(other.available(), 0);
+    }
+
+    #[test]
+    fn test_rate_limited_round_trips_through_diesel_error() {
+        let error: diesel::result::Error = RateLimited {
+            retry_after: Duration::from_secs(1),
+        }
+        .into();
+        assert_eq!(
+            RateLimited::from_error(&error).map(|e| e.retry_after),
+            Some(Duration::from_secs(1))
+        );
+        assert!(RateLimited::from_error(&diesel::result::Error::NotFound).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_does_not_wait() {
+        let policy = RateLimitPolicy::builder()
+            .requests_per_window(1)
+            .window(Duration::from_secs(60))
+            .behavior(RateLimitBehavior::FailFast)
+            .build();
+
+        assert!(policy.acquire().await.is_ok());
+        assert!(policy.acquire().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue_waits_for_next_slot() {
+        let policy = RateLimitPolicy::new(1, Duration::from_millis(50));
+
+        policy.acquire().await.unwrap();
+        let start = Instant::now();
+        policy.acquire().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_wait_gives_slot_back() {
+        let policy = RateLimitPolicy::new(1, Duration::from_secs(60));
+        policy.acquire().await.unwrap();
+
+        let waited = tokio::time::timeout(Duration::from_millis(10), policy.acquire()).await;
+        assert!(waited.is_err());
+
+        // Only the first request's token is missing
+        let retry_after = policy.try_acquire().unwrap_err().retry_after;
+        assert!(retry_after <= Duration::from_secs(60));
+        assert!(retry_after > Duration::from_secs(59));
+    }
+}
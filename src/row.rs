@@ -1,15 +1,14 @@
-use std::{
-    cell::{Ref, RefCell},
-    rc::Rc,
-};
-
 use diesel::row::{Field, PartialRow, Row, RowIndex, RowSealed};
 use wasm_bindgen::JsValue;
 
 use crate::{backend::D1Backend, value::D1Value};
 
+/// A row from a D1 query result (WASM version)
+///
+/// Values are kept in the column order of the query, which is the order
+/// Diesel decodes `Queryable` fields in.
 pub struct D1Row {
-    _js_obj: Rc<RefCell<JsValue>>,
+    values: Vec<JsValue>,
     field_vec: Vec<String>,
 }
 
@@ -18,12 +17,27 @@ unsafe impl Send for D1Row {}
 unsafe impl Sync for D1Row {}
 
 impl D1Row {
+    /// Create a new row from a JS object and field names
+    ///
+    /// The row's columns are the named properties of the object, in the
+    /// order of `field_vec`.
     pub fn new(js_value: JsValue, field_vec: Vec<String>) -> Self {
-        Self {
-            // again
-            _js_obj: Rc::new(RefCell::new(js_value)),
-            field_vec,
-        }
+        let values = field_vec
+            .iter()
+            .map(|name| {
+                js_sys::Reflect::get(&js_value, &JsValue::from_str(name))
+                    .unwrap_or(JsValue::UNDEFINED)
+            })
+            .collect();
+        Self { values, field_vec }
+    }
+
+    /// Create a new row from column values and their names, in column order
+    ///
+    /// Unlike [`D1Row::new`], this keeps columns that share a name, such as
+    /// the `id` of both sides of a join.
+    pub fn from_values(values: Vec<JsValue>, field_vec: Vec<String>) -> Self {
+        Self { values, field_vec }
     }
 }
 
@@ -48,10 +62,9 @@ impl<'stmt> Row<'stmt, D1Backend> for D1Row {
         Self: diesel::row::RowIndex<I>,
     {
         let index = self.idx(idx)?;
-        let name = self.field_vec.get(index)?;
         Some(D1Field {
-            name: name.to_string(),
-            row: self._js_obj.borrow(),
+            name: self.field_vec.get(index)?,
+            value: self.values.get(index),
         })
     }
 
@@ -79,19 +92,21 @@ impl RowIndex<&str> for D1Row {
     }
 }
 
+/// A field from a D1 row (WASM version)
 pub struct D1Field<'stmt> {
-    row: Ref<'stmt, JsValue>,
-    name: String,
+    name: &'stmt str,
+    value: Option<&'stmt JsValue>,
 }
 
 impl<'stmt> Field<'stmt, D1Backend> for D1Field<'stmt> {
     fn field_name(&self) -> Option<&str> {
-        Some(&self.name)
+        Some(self.name)
     }
 
     fn value(&self) -> Option<D1Value> {
-        let js_value = js_sys::Reflect::get(&self.row, &self.name.clone().into()).ok()?;
-
-        Some(D1Value::new(js_value))
+        // SQL NULL has no value, which Diesel decodes as `None`
+        self.value
+            .filter(|v| !v.is_null() && !v.is_undefined())
+            .map(|v| D1Value::new(v.clone()))
     }
 }
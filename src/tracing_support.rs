@@ -32,6 +32,8 @@
 //! span.record_success(10, std::time::Duration::from_millis(50));
 //! ```
 
+#[cfg(any(feature = "wasm", feature = "http"))]
+use std::sync::Arc;
 use std::time::Duration;
 
 /// The backend type for tracing
@@ -120,6 +122,14 @@ impl ErrorClass {
         }
     }
 
+    /// Check if a failure of this class is likely to succeed when retried
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ErrorClass::RateLimit | ErrorClass::Timeout | ErrorClass::Network
+        )
+    }
+
     /// Classify an error from an HTTP status code
     pub fn from_http_status(status: u16) -> Self {
         match status {
@@ -222,6 +232,8 @@ pub struct D1Span {
     pub response_status: Option<u16>,
     /// Number of retry attempts
     pub retry_count: Option<u32>,
+    /// Time spent waiting for a concurrency permit
+    pub permit_wait: Option<Duration>,
 }
 
 impl D1Span {
@@ -239,6 +251,7 @@ impl D1Span {
             request_id: None,
             response_status: None,
             retry_count: None,
+            permit_wait: None,
         }
     }
 
@@ -303,6 +316,11 @@ impl D1Span {
         self.retry_count = Some(attempt);
     }
 
+    /// Record how long the operation waited for a concurrency permit
+    pub fn record_permit_wait(&mut self, wait: Duration) {
+        self.permit_wait = Some(wait);
+    }
+
     /// Check if the span represents a failure
     pub fn is_error(&self) -> bool {
         self.error_class.is_some()
@@ -320,6 +338,10 @@ impl D1Span {
             parts.push(format!("duration={:?}", duration));
         }
 
+        if let Some(ref wait) = self.permit_wait {
+            parts.push(format!("permit_wait={:?}", wait));
+        }
+
         if let Some(rows) = self.rows_read {
             parts.push(format!("rows_read={}", rows));
         }
@@ -435,6 +457,92 @@ impl SpanTimer {
     }
 }
 
+/// Elapsed-time clock that also works on wasm32, where `Instant` panics
+#[cfg(any(feature = "wasm", feature = "http"))]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch {
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    start: std::time::Instant,
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    start_millis: f64,
+}
+
+#[cfg(any(feature = "wasm", feature = "http"))]
+impl Stopwatch {
+    /// Start measuring from now
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+            start: std::time::Instant::now(),
+            #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+            start_millis: js_sys::Date::now(),
+        }
+    }
+
+    /// Time elapsed since the stopwatch was started
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+        {
+            self.start.elapsed()
+        }
+        #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+        {
+            Duration::from_secs_f64((js_sys::Date::now() - self.start_millis).max(0.0) / 1000.0)
+        }
+    }
+}
+
+/// Span for a single connection operation, emitted when it finishes
+///
+/// Nothing is captured or emitted when the connection has no span emitter.
+#[cfg(any(feature = "wasm", feature = "http"))]
+pub(crate) struct QuerySpan {
+    span: D1Span,
+    stopwatch: Stopwatch,
+    emitter: Option<Arc<dyn SpanEmitter + Send + Sync>>,
+}
+
+#[cfg(any(feature = "wasm", feature = "http"))]
+impl QuerySpan {
+    /// Start a span for `sql`, if it is known, on the given backend
+    pub(crate) fn start(
+        operation: SpanOperation,
+        backend: BackendType,
+        sql: Option<&str>,
+        emitter: Option<&Arc<dyn SpanEmitter + Send + Sync>>,
+    ) -> Self {
+        let mut span = D1Span::new(operation);
+        span.backend = Some(backend);
+        if emitter.is_some() {
+            span.sql = sql.map(str::to_string);
+        }
+        Self {
+            span,
+            stopwatch: Stopwatch::start(),
+            emitter: emitter.cloned(),
+        }
+    }
+
+    /// Get mutable access to the span
+    pub(crate) fn span_mut(&mut self) -> &mut D1Span {
+        &mut self.span
+    }
+
+    /// Record the outcome and duration of the operation and emit the span
+    pub(crate) fn finish<T>(mut self, result: &diesel::QueryResult<T>) {
+        let Some(emitter) = self.emitter.take() else {
+            return;
+        };
+        self.span.duration = Some(self.stopwatch.elapsed());
+        if let Err(error) = result {
+            let message = error.to_string();
+            self.span
+                .record_error(ErrorClass::from_error_message(&message), message);
+        }
+        emitter.emit_span(&self.span);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -475,6 +583,16 @@ mod tests {
         assert_eq!(ErrorClass::from_http_status(500), ErrorClass::Unknown);
     }
 
+    #[test]
+    fn test_error_class_is_transient() {
+        assert!(ErrorClass::from_http_status(429).is_transient());
+        assert!(ErrorClass::from_http_status(503).is_transient());
+        assert!(ErrorClass::from_http_status(504).is_transient());
+        assert!(!ErrorClass::from_http_status(400).is_transient());
+        assert!(!ErrorClass::from_http_status(401).is_transient());
+        assert!(!ErrorClass::from_http_status(500).is_transient());
+    }
+
     #[test]
     fn test_error_class_from_error_message() {
         assert_eq!(
@@ -561,6 +679,11 @@ mod tests {
         assert!(summary.contains("op=exec"));
         assert!(summary.contains("backend=http"));
         assert!(summary.contains("rows_written=5"));
+        assert!(!summary.contains("permit_wait"));
+
+        span.record_permit_wait(Duration::from_millis(3));
+        assert_eq!(span.permit_wait, Some(Duration::from_millis(3)));
+        assert!(span.summary().contains("permit_wait=3ms"));
     }
 
     #[test]
@@ -616,4 +739,51 @@ mod tests {
         // Just verify it doesn't panic
         emitter.emit_span(&span);
     }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_query_span_emits_outcome() {
+        let emitter = Arc::new(TestSpanEmitter::new());
+        let shared: Arc<dyn SpanEmitter + Send + Sync> = emitter.clone();
+
+        let mut span = QuerySpan::start(
+            SpanOperation::Execute,
+            BackendType::Http,
+            Some("SELECT 1"),
+            Some(&shared),
+        );
+        span.span_mut().record_permit_wait(Duration::from_millis(2));
+        span.finish(&Ok(()));
+
+        let error: diesel::QueryResult<()> = Err(diesel::result::Error::NotFound);
+        QuerySpan::start(
+            SpanOperation::Execute,
+            BackendType::Http,
+            Some("x"),
+            Some(&shared),
+        )
+        .finish(&error);
+
+        let spans = emitter.get_spans();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].sql.as_deref(), Some("SELECT 1"));
+        assert_eq!(spans[0].backend, Some(BackendType::Http));
+        assert_eq!(spans[0].permit_wait, Some(Duration::from_millis(2)));
+        assert!(spans[0].duration.is_some());
+        assert!(!spans[0].is_error());
+        assert!(spans[1].is_error());
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_query_span_without_emitter_skips_sql() {
+        let mut span = QuerySpan::start(
+            SpanOperation::Execute,
+            BackendType::Wasm,
+            Some("SELECT 1"),
+            None,
+        );
+        assert!(span.span_mut().sql.is_none());
+        span.finish(&Ok(()));
+    }
 }
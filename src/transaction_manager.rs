@@ -4,6 +4,21 @@
 //! Since D1 is based on SQLite and uses the batch() API for atomicity,
 //! transactions are emulated by collecting statements and executing them
 //! atomically on commit.
+//!
+//! Write statements issued inside a transaction are buffered on the
+//! connection instead of being sent. Their affected row count is therefore
+//! reported as `0`. On commit the buffer is sent as a single batch, and on
+//! rollback it is simply dropped. If the commit batch fails, the transaction
+//! manager is marked broken so that connection pools discard the connection.
+//!
+//! Nested transactions behave like savepoints. Each level remembers how long
+//! the queue was when it began; rolling back a level truncates the queue to
+//! that marker, and committing a level keeps its statements in the outer
+//! level's share of the queue.
+//!
+//! Because writes are buffered, a read issued inside a transaction cannot
+//! see them. What happens to such a read is decided by the connection's
+//! [`TransactionReadPolicy`].
 
 use std::cell::Cell;
 
@@ -11,6 +26,27 @@ use async_trait::async_trait;
 use diesel::{connection::TransactionManagerStatus, result::Error as DieselError, QueryResult};
 use diesel_async::TransactionManager;
 
+#[cfg(feature = "wasm")]
+use crate::utils::SendableFuture;
+
+/// What a connection does with a read issued inside a transaction
+///
+/// Reads are never buffered, so they cannot observe the writes queued by
+/// the enclosing transaction. The policy makes that trade-off explicit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionReadPolicy {
+    /// Reject the read with an error
+    Error,
+    /// Run the read immediately against the committed database state
+    #[default]
+    ReadCommitted,
+    /// Send the queued writes as one batch, then run the read
+    ///
+    /// The flushed writes are durable from that point on; a later rollback
+    /// only discards the statements queued after the flush.
+    FlushAndContinue,
+}
+
 /// Transaction Manager for D1 connections
 ///
 /// D1 doesn't have traditional transaction support, but we can emulate
@@ -36,8 +72,12 @@ pub struct D1TransactionManager {
     pub(crate) is_in_transaction: Cell<bool>,
     /// Transaction depth for nested transaction tracking
     pub(crate) depth: Cell<u32>,
-    /// Transaction status
+    /// Transaction status, `InError` after a commit failed
     pub(crate) status: TransactionManagerStatus,
+    /// Queue length at the start of each open transaction level
+    pub(crate) savepoints: Vec<usize>,
+    /// How reads inside a transaction are handled
+    pub(crate) read_policy: TransactionReadPolicy,
 }
 
 impl D1TransactionManager {
@@ -55,6 +95,56 @@ impl D1TransactionManager {
     pub fn transaction_depth(&self) -> u32 {
         self.depth.get()
     }
+
+    /// Get the policy applied to reads issued inside a transaction
+    pub fn read_policy(&self) -> TransactionReadPolicy {
+        self.read_policy
+    }
+
+    /// Apply the read policy to a read about to run on the connection
+    ///
+    /// Returns whether the queued writes must be flushed before the read.
+    /// Reads outside a transaction always run directly.
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    pub(crate) fn prepare_read(&self) -> QueryResult<bool> {
+        if !self.is_in_transaction() {
+            return Ok(false);
+        }
+
+        match self.read_policy {
+            TransactionReadPolicy::Error => Err(DieselError::DatabaseError(
+                diesel::result::DatabaseErrorKind::Unknown,
+                Box::new(crate::utils::D1Error::new(
+                    "Reads inside a transaction are disabled by TransactionReadPolicy::Error",
+                )),
+            )),
+            TransactionReadPolicy::ReadCommitted => Ok(false),
+            TransactionReadPolicy::FlushAndContinue => Ok(true),
+        }
+    }
+
+    /// Forget the savepoint markers after the queue was sent mid-transaction
+    ///
+    /// Flushed statements can no longer be rolled back, so every open level
+    /// now starts at the beginning of the (empty) queue.
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    pub(crate) fn reset_savepoints(&mut self) {
+        self.savepoints.iter_mut().for_each(|marker| *marker = 0);
+    }
+
+    /// Mark the manager as broken if a commit failed
+    ///
+    /// A failed flush leaves the transaction's outcome unknown, so the
+    /// connection is reported as broken to pools and no new transaction can
+    /// begin. Leaving a transaction that was never opened is only a usage
+    /// error and does not break the manager.
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    pub(crate) fn record_outcome<T>(&mut self, result: QueryResult<T>) -> QueryResult<T> {
+        if matches!(&result, Err(error) if !matches!(error, DieselError::NotInTransaction)) {
+            self.status.set_in_error();
+        }
+        result
+    }
 }
 
 /// Trait for connections that support D1 transactions
@@ -73,7 +163,6 @@ pub trait D1TransactionConnection {
 
 /// Trait for HTTP connections that support D1 transactions
 #[cfg(feature = "http")]
-#[allow(dead_code)]
 pub trait D1HttpTransactionConnection {
     /// Get the transaction manager
     fn d1_transaction_manager(&mut self) -> &mut D1TransactionManager;
@@ -88,11 +177,15 @@ impl TransactionManager<crate::wasm_connection::D1Connection> for D1TransactionM
     type TransactionStateData = Self;
 
     async fn begin_transaction(conn: &mut crate::wasm_connection::D1Connection) -> QueryResult<()> {
-        let depth = conn.transaction_manager.depth.get();
-        conn.transaction_manager.depth.set(depth + 1);
+        conn.transaction_manager.status.transaction_depth()?;
+        let marker = conn.transaction_queries().len();
+        let tm = conn.d1_transaction_manager();
+        let depth = tm.depth.get();
+        tm.depth.set(depth + 1);
+        tm.savepoints.push(marker);
 
         if depth == 0 {
-            conn.transaction_manager.is_in_transaction.set(true);
+            tm.is_in_transaction.set(true);
         }
 
         Ok(())
@@ -101,34 +194,48 @@ impl TransactionManager<crate::wasm_connection::D1Connection> for D1TransactionM
     async fn rollback_transaction(
         conn: &mut crate::wasm_connection::D1Connection,
     ) -> QueryResult<()> {
-        let depth = conn.transaction_manager.depth.get();
+        let tm = conn.d1_transaction_manager();
+        let depth = tm.depth.get();
 
         if depth == 0 {
             return Err(DieselError::NotInTransaction);
         }
 
-        conn.transaction_manager.depth.set(depth - 1);
+        tm.depth.set(depth - 1);
+        let marker = tm.savepoints.pop().unwrap_or(0);
 
         if depth == 1 {
-            conn.transaction_manager.is_in_transaction.set(false);
+            tm.is_in_transaction.set(false);
         }
 
+        // Nothing has been sent yet, so dropping the statements queued since
+        // this level began is the rollback
+        conn.transaction_queries().truncate(marker);
+
         Ok(())
     }
 
     async fn commit_transaction(
         conn: &mut crate::wasm_connection::D1Connection,
     ) -> QueryResult<()> {
-        let depth = conn.transaction_manager.depth.get();
+        let tm = conn.d1_transaction_manager();
+        let depth = tm.depth.get();
 
         if depth == 0 {
             return Err(DieselError::NotInTransaction);
         }
 
-        conn.transaction_manager.depth.set(depth - 1);
+        tm.depth.set(depth - 1);
+        // The committed level's statements now belong to the enclosing level
+        tm.savepoints.pop();
 
         if depth == 1 {
-            conn.transaction_manager.is_in_transaction.set(false);
+            tm.is_in_transaction.set(false);
+
+            // Send every buffered write through one `batch()` call so D1
+            // applies all of them or none of them
+            let result = SendableFuture(conn.flush_transaction_queries()).await;
+            conn.d1_transaction_manager().record_outcome(result)?;
         }
 
         Ok(())
@@ -149,11 +256,15 @@ impl TransactionManager<crate::http_connection::D1HttpConnection> for D1Transact
     async fn begin_transaction(
         conn: &mut crate::http_connection::D1HttpConnection,
     ) -> QueryResult<()> {
-        let depth = conn.transaction_manager.depth.get();
-        conn.transaction_manager.depth.set(depth + 1);
+        conn.transaction_manager.status.transaction_depth()?;
+        let marker = conn.transaction_queries().len();
+        let tm = conn.d1_transaction_manager();
+        let depth = tm.depth.get();
+        tm.depth.set(depth + 1);
+        tm.savepoints.push(marker);
 
         if depth == 0 {
-            conn.transaction_manager.is_in_transaction.set(true);
+            tm.is_in_transaction.set(true);
         }
 
         Ok(())
@@ -162,34 +273,48 @@ impl TransactionManager<crate::http_connection::D1HttpConnection> for D1Transact
     async fn rollback_transaction(
         conn: &mut crate::http_connection::D1HttpConnection,
     ) -> QueryResult<()> {
-        let depth = conn.transaction_manager.depth.get();
+        let tm = conn.d1_transaction_manager();
+        let depth = tm.depth.get();
 
         if depth == 0 {
             return Err(DieselError::NotInTransaction);
         }
 
-        conn.transaction_manager.depth.set(depth - 1);
+        tm.depth.set(depth - 1);
+        let marker = tm.savepoints.pop().unwrap_or(0);
 
         if depth == 1 {
-            conn.transaction_manager.is_in_transaction.set(false);
+            tm.is_in_transaction.set(false);
         }
 
+        // Nothing has been sent yet, so dropping the statements queued since
+        // this level began is the rollback
+        conn.transaction_queries().truncate(marker);
+
         Ok(())
     }
 
     async fn commit_transaction(
         conn: &mut crate::http_connection::D1HttpConnection,
     ) -> QueryResult<()> {
-        let depth = conn.transaction_manager.depth.get();
+        let tm = conn.d1_transaction_manager();
+        let depth = tm.depth.get();
 
         if depth == 0 {
             return Err(DieselError::NotInTransaction);
         }
 
-        conn.transaction_manager.depth.set(depth - 1);
+        tm.depth.set(depth - 1);
+        // The committed level's statements now belong to the enclosing level
+        tm.savepoints.pop();
 
         if depth == 1 {
-            conn.transaction_manager.is_in_transaction.set(false);
+            tm.is_in_transaction.set(false);
+
+            // Send every buffered write in one request so D1 applies all of
+            // them or none of them
+            let result = conn.flush_transaction_queries().await;
+            conn.d1_transaction_manager().record_outcome(result)?;
         }
 
         Ok(())
@@ -246,4 +371,56 @@ mod tests {
         assert!(!tm.is_in_transaction());
         assert_eq!(tm.transaction_depth(), 0);
     }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_failed_commit_breaks_manager() {
+        let mut tm = D1TransactionManager::new();
+
+        // Leaving a transaction that was never opened is not a failure
+        let result: QueryResult<()> = Err(DieselError::NotInTransaction);
+        assert!(tm.record_outcome(result).is_err());
+        assert!(tm.status.transaction_depth().is_ok());
+
+        let result: QueryResult<()> = Err(DieselError::RollbackTransaction);
+        assert!(tm.record_outcome(result).is_err());
+        assert!(matches!(
+            tm.status.transaction_depth(),
+            Err(DieselError::BrokenTransactionManager)
+        ));
+    }
+
+    #[test]
+    fn test_default_read_policy() {
+        let tm = D1TransactionManager::new();
+        assert_eq!(tm.read_policy(), TransactionReadPolicy::ReadCommitted);
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_prepare_read_follows_policy() {
+        let mut tm = D1TransactionManager::new();
+
+        // Outside a transaction every policy reads directly
+        tm.read_policy = TransactionReadPolicy::Error;
+        assert!(!tm.prepare_read().unwrap());
+
+        tm.is_in_transaction.set(true);
+        assert!(tm.prepare_read().is_err());
+
+        tm.read_policy = TransactionReadPolicy::ReadCommitted;
+        assert!(!tm.prepare_read().unwrap());
+
+        tm.read_policy = TransactionReadPolicy::FlushAndContinue;
+        assert!(tm.prepare_read().unwrap());
+    }
+
+    #[cfg(any(feature = "wasm", feature = "http"))]
+    #[test]
+    fn test_reset_savepoints() {
+        let mut tm = D1TransactionManager::new();
+        tm.savepoints = vec![0, 2, 5];
+        tm.reset_savepoints();
+        assert_eq!(tm.savepoints, vec![0, 0, 0]);
+    }
 }
@@ -0,0 +1,357 @@
+//! Pluggable HTTP transport for the D1 REST API
+//!
+//! [`D1HttpConnection`](crate::D1HttpConnection) serializes every request
+//! itself and hands it to a [`D1Transport`] to deliver. The default
+//! [`ReqwestTransport`] sends it with `reqwest`; a custom transport can route
+//! requests through an egress proxy or sign them, and [`MemoryTransport`]
+//! answers with scripted responses so tests need no sockets.
+//!
+//! # Example
+//!
+//! ```
+//! use diesel_d1::transport::{MemoryTransport, TransportResponse};
+//!
+//! let transport = MemoryTransport::new();
+//! transport.push_response(
+//!     TransportResponse::new(503, "unavailable").with_header("Retry-After", "1"),
+//! );
+//! assert!(transport.requests().is_empty());
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::tracing_support::ErrorClass;
+
+/// Result type of [`D1Transport`] operations
+pub type TransportResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A serialized request to the D1 REST API
+///
+/// Requests are always sent as a `POST` with a JSON body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportRequest {
+    /// Endpoint URL
+    pub url: String,
+    /// Header names and values, in the order they were added
+    pub headers: Vec<(String, String)>,
+    /// JSON request body
+    pub body: Vec<u8>,
+}
+
+impl TransportRequest {
+    /// Get the value of a header, matching its name case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// A response from the D1 REST API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Header names and values
+    pub headers: Vec<(String, String)>,
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+impl TransportResponse {
+    /// Create a response without headers
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Add a header
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Get the value of a header, matching its name case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Check if the status is in the 2xx range
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Find the first header called `name`, ignoring case
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Delivers requests to the D1 REST API
+///
+/// An error means no response was received; responses with an error status
+/// are returned as `Ok` and handled by the connection, which also applies
+/// retries and rate limits around the transport.
+///
+/// # Example
+///
+/// ```
+/// use async_trait::async_trait;
+/// use diesel_d1::transport::{
+///     D1Transport, ReqwestTransport, TransportRequest, TransportResponse, TransportResult,
+/// };
+///
+/// /// Adds a signature header to every request
+/// struct SigningTransport {
+///     inner: ReqwestTransport,
+/// }
+///
+/// #[async_trait]
+/// impl D1Transport for SigningTransport {
+///     async fn send(&self, mut request: TransportRequest) -> TransportResult<TransportResponse> {
+///         let signature = format!("{:x}", request.body.len());
+///         request.headers.push(("X-Signature".to_string(), signature));
+///         self.inner.send(request).await
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait D1Transport: Send + Sync {
+    /// Send `request` and return the response
+    async fn send(&self, request: TransportRequest) -> TransportResult<TransportResponse>;
+}
+
+#[async_trait]
+impl<T: D1Transport + ?Sized> D1Transport for Arc<T> {
+    async fn send(&self, request: TransportRequest) -> TransportResult<TransportResponse> {
+        (**self).send(request).await
+    }
+}
+
+/// Classify a failure to receive any response
+///
+/// Timeouts reported by `reqwest` or as an I/O error are
+/// [`ErrorClass::Timeout`]; any other failure is [`ErrorClass::Network`].
+pub(crate) fn transport_error_class(error: &(dyn std::error::Error + 'static)) -> ErrorClass {
+    let timed_out = match error.downcast_ref::<reqwest::Error>() {
+        Some(error) => error.is_timeout(),
+        None => error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|error| error.kind() == std::io::ErrorKind::TimedOut),
+    };
+
+    if timed_out {
+        ErrorClass::Timeout
+    } else {
+        ErrorClass::Network
+    }
+}
+
+/// Transport that sends requests with a `reqwest` client
+///
+/// Used by [`D1HttpConnection`](crate::D1HttpConnection) unless configured
+/// otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    /// Create a transport with a default client
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a transport that sends through `client`
+    pub fn from_client(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl D1Transport for ReqwestTransport {
+    async fn send(&self, request: TransportRequest) -> TransportResult<TransportResponse> {
+        let mut builder = self.client.post(&request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+
+        let response = builder.body(request.body).send().await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.bytes().await?.to_vec();
+
+        Ok(TransportResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Transport that answers with scripted responses and records requests
+///
+/// Responses are returned in the order they were pushed; a request with no
+/// response left fails like a broken connection. Wrap it in an [`Arc`] to
+/// keep a handle for inspecting the requests after handing it to a
+/// connection.
+///
+/// # Example
+///
+/// ```
+/// use diesel_d1::transport::{MemoryTransport, TransportResponse};
+///
+/// let transport = MemoryTransport::new();
+/// transport.push_response(TransportResponse::new(
+///     200,
+///     r#"{"success":true,"errors":[],"result":[]}"#,
+/// ));
+/// transport.push_error("connection reset");
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    responses: Mutex<VecDeque<Result<TransportResponse, String>>>,
+    requests: Mutex<Vec<TransportRequest>>,
+}
+
+impl MemoryTransport {
+    /// Create a transport with no scripted responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the response to a request
+    pub fn push_response(&self, response: TransportResponse) {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses.push_back(Ok(response));
+        }
+    }
+
+    /// Queue a failure to receive any response, such as a dropped connection
+    pub fn push_error(&self, message: impl Into<String>) {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses.push_back(Err(message.into()));
+        }
+    }
+
+    /// Get the requests sent so far, in order
+    pub fn requests(&self) -> Vec<TransportRequest> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl D1Transport for MemoryTransport {
+    async fn send(&self, request: TransportRequest) -> TransportResult<TransportResponse> {
+        let url = request.url.clone();
+        self.requests
+            .lock()
+            .map_err(|e| e.to_string())?
+            .push(request);
+
+        match self
+            .responses
+            .lock()
+            .map_err(|e| e.to_string())?
+            .pop_front()
+        {
+            Some(Ok(response)) => Ok(response),
+            Some(Err(message)) => Err(message.into()),
+            None => Err(format!("no response scripted for {}", url).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: &str) -> TransportRequest {
+        TransportRequest {
+            url: "http://d1.test/raw".to_string(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_header_lookup_ignores_case() {
+        let response = TransportResponse::new(429, "").with_header("Retry-After", "5");
+        assert_eq!(response.header("retry-after"), Some("5"));
+        assert_eq!(response.header("RETRY-AFTER"), Some("5"));
+        assert_eq!(response.header("x-missing"), None);
+
+        assert_eq!(
+            request("{}").header("content-type"),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn test_response_is_success() {
+        assert!(TransportResponse::new(200, "").is_success());
+        assert!(TransportResponse::new(204, "").is_success());
+        assert!(!TransportResponse::new(301, "").is_success());
+        assert!(!TransportResponse::new(503, "").is_success());
+    }
+
+    #[tokio::test]
+    async fn test_memory_transport_scripts_responses_in_order() {
+        let transport = MemoryTransport::new();
+        transport.push_response(TransportResponse::new(503, "unavailable"));
+        transport.push_error("connection reset");
+        transport.push_response(TransportResponse::new(200, "ok"));
+
+        let first = transport.send(request("1")).await.unwrap();
+        assert_eq!(first.status, 503);
+        let second = transport.send(request("2")).await.unwrap_err();
+        assert_eq!(second.to_string(), "connection reset");
+        let third = transport.send(request("3")).await.unwrap();
+        assert_eq!(third.body, b"ok");
+
+        // Nothing left to answer with
+        assert!(transport.send(request("4")).await.is_err());
+
+        let bodies: Vec<_> = transport
+            .requests()
+            .into_iter()
+            .map(|request| request.body)
+            .collect();
+        assert_eq!(bodies, vec![b"1", b"2", b"3", b"4"]);
+    }
+
+    #[test]
+    fn test_transport_error_class() {
+        let timed_out = std::io::Error::new(std::io::ErrorKind::TimedOut, "connection reset");
+        assert_eq!(transport_error_class(&timed_out), ErrorClass::Timeout);
+
+        // The kind decides, not the message
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "timed out");
+        assert_eq!(transport_error_class(&reset), ErrorClass::Network);
+
+        let other: Box<dyn std::error::Error + Send + Sync> = "request timed out".into();
+        assert_eq!(transport_error_class(other.as_ref()), ErrorClass::Network);
+    }
+
+    #[tokio::test]
+    async fn test_arc_transport_shares_state() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response(TransportResponse::new(200, "ok"));
+
+        let shared: Arc<dyn D1Transport> = transport.clone();
+        shared.send(request("{}")).await.unwrap();
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -196,6 +196,22 @@ impl std::fmt::Display for D1Error {
 
 impl std::error::Error for D1Error {}
 
+/// Build the Diesel error for a failure message returned by D1
+///
+/// Failures raised by a batch guard are reported as
+/// [`GuardFailed`](crate::batch::GuardFailed) instead of an unknown error.
+#[cfg(any(feature = "wasm", feature = "http"))]
+pub(crate) fn d1_database_error(message: impl Into<String>) -> diesel::result::Error {
+    let message = message.into();
+    match crate::batch::GuardFailed::from_message(&message) {
+        Some(guard) => guard.into(),
+        None => diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new(D1Error::new(message)),
+        ),
+    }
+}
+
 impl DatabaseErrorInformation for D1Error {
     fn message(&self) -> &str {
         &self.message
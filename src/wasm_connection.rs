@@ -3,31 +3,47 @@
 //! This module provides the D1Connection type that uses the WASM bindings
 //! to interact with Cloudflare D1 in Workers environments.
 
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use diesel::{
     connection::{ConnectionSealed, Instrumentation},
+    deserialize::FromSqlRow,
     query_builder::{AsQuery, QueryFragment, QueryId},
     ConnectionResult, QueryResult,
 };
 use diesel_async::{AsyncConnection, SimpleAsyncConnection};
 use futures_util::{
-    future::BoxFuture,
+    future::{try_join_all, BoxFuture},
     stream::{self, BoxStream},
     FutureExt, StreamExt,
 };
 use js_sys::{Array, Object, Reflect};
-use wasm_bindgen::JsValue;
+use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use worker::console_error;
 
 use crate::{
     backend::D1Backend,
+    batch::{
+        decode_rows, render_query, BatchBuilder, BatchLoad, BatchResult, BatchStatement,
+        BoundValue, StatementMeta, StatementResult,
+    },
     bind_collector::D1BindCollector,
     binding::{D1Database, D1PreparedStatement, D1Result},
-    query_builder::D1QueryBuilder,
+    cache::{
+        result_cache_key, CacheStatsSnapshot, PreparedStatementCache, ResultCache,
+        ResultCacheConfig, SqlCache, StatementCacheConfig,
+    },
+    cache_store::{CachedRows, ResultCacheStore},
+    concurrency::{ConcurrencyPermit, QueryConcurrencyPolicy},
+    limits::D1Limits,
     row::D1Row,
-    transaction_manager::D1TransactionManager,
-    utils::{D1Error, SendableFuture},
+    tracing_support::{BackendType, D1Span, QuerySpan, SpanEmitter, SpanOperation},
+    transaction_manager::{D1TransactionConnection, D1TransactionManager, TransactionReadPolicy},
+    utils::{d1_database_error, D1Error, SendableFuture},
 };
 
 /// D1 Connection for WASM/Cloudflare Workers environment.
@@ -43,11 +59,23 @@ use crate::{
 /// let conn = D1Connection::new(env, "MY_DATABASE");
 /// ```
 pub struct D1Connection {
-    #[allow(dead_code)]
+    /// Bound write statements buffered while a transaction is open
     transaction_queries: Vec<D1PreparedStatement>,
     /// Transaction manager (public for TransactionManager trait access)
     pub(crate) transaction_manager: D1TransactionManager,
     binding: D1Database,
+    /// Platform limits checked before statements are sent
+    limits: D1Limits,
+    /// Prepared statement handles reused across queries
+    statement_cache: PreparedStatementCache,
+    /// SQL text of static queries
+    sql_cache: SqlCache,
+    /// Results of `load_cached` queries
+    result_cache: ResultCache,
+    /// Limit on queries in flight, shared with other connections
+    concurrency_policy: Option<QueryConcurrencyPolicy>,
+    /// Receives a span for every query sent
+    span_emitter: Option<Arc<dyn SpanEmitter + Send + Sync>>,
     /// Instrumentation for the connection
     instrumentation: Option<Box<dyn Instrumentation>>,
 }
@@ -65,6 +93,12 @@ impl D1Connection {
             transaction_queries: Vec::default(),
             transaction_manager: D1TransactionManager::default(),
             binding,
+            limits: D1Limits::default(),
+            statement_cache: PreparedStatementCache::new(StatementCacheConfig::default()),
+            sql_cache: SqlCache::default(),
+            result_cache: ResultCache::new(ResultCacheConfig::disabled()),
+            concurrency_policy: None,
+            span_emitter: None,
             instrumentation: None,
         }
     }
@@ -73,6 +107,393 @@ impl D1Connection {
     pub fn binding(&self) -> &D1Database {
         &self.binding
     }
+
+    /// Set how reads issued inside a transaction are handled
+    ///
+    /// Defaults to [`TransactionReadPolicy::ReadCommitted`].
+    pub fn with_read_policy(mut self, policy: TransactionReadPolicy) -> Self {
+        self.transaction_manager.read_policy = policy;
+        self
+    }
+
+    /// Set the platform limits checked before statements are sent
+    ///
+    /// Defaults to Cloudflare's published D1 limits.
+    pub fn with_limits(mut self, limits: D1Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Configure the cache of prepared statement handles
+    ///
+    /// Cache hits skip `prepare` and only bind the new parameters. Enabled
+    /// with [`StatementCacheConfig::default`] unless configured otherwise.
+    pub fn with_statement_cache(mut self, config: StatementCacheConfig) -> Self {
+        self.statement_cache = PreparedStatementCache::new(config);
+        self
+    }
+
+    /// Get the hit and miss counts of the prepared statement cache
+    pub fn statement_cache_stats(&self) -> CacheStatsSnapshot {
+        self.statement_cache.stats()
+    }
+
+    /// Enable the result cache used by [`load_cached`](Self::load_cached)
+    ///
+    /// Disabled unless configured, in which case `load_cached` always
+    /// queries D1.
+    pub fn with_result_cache(mut self, config: ResultCacheConfig) -> Self {
+        self.result_cache = ResultCache::new(config);
+        self
+    }
+
+    /// Enable the result cache used by [`load_cached`](Self::load_cached)
+    /// with a custom store, such as a
+    /// [`WorkersCacheStore`](crate::cache_store::WorkersCacheStore)
+    pub fn with_result_cache_store(mut self, store: impl ResultCacheStore + 'static) -> Self {
+        self.result_cache = ResultCache::with_store(store);
+        self
+    }
+
+    /// Get the hit and miss counts of the result cache
+    pub fn result_cache_stats(&self) -> CacheStatsSnapshot {
+        self.result_cache.stats()
+    }
+
+    /// Limit how many queries may be in flight at once
+    ///
+    /// Every statement or batch sent to D1, including transaction commits,
+    /// holds a permit from the policy while it is outstanding. Pass clones of
+    /// the same policy to every connection in the isolate to share one limit.
+    ///
+    /// Each query's outcome is reported on its permit, so an
+    /// [adaptive](crate::concurrency::AdaptiveConcurrency) policy backs off
+    /// when D1 starts rate limiting or timing out.
+    pub fn with_concurrency_policy(mut self, policy: QueryConcurrencyPolicy) -> Self {
+        self.concurrency_policy = Some(policy);
+        self
+    }
+
+    /// Emit a [`D1Span`](crate::D1Span) for every statement or batch sent
+    /// to D1
+    pub fn with_span_emitter(mut self, emitter: Arc<dyn SpanEmitter + Send + Sync>) -> Self {
+        self.span_emitter = Some(emitter);
+        self
+    }
+
+    /// Wait for a permit from the concurrency policy, if one is set,
+    /// recording the wait on `span`
+    async fn acquire_permit(&self, span: &mut QuerySpan) -> Option<ConcurrencyPermit> {
+        let policy = self.concurrency_policy.as_ref()?;
+        let (permit, waited) = policy.acquire_timed().await;
+        span.span_mut().record_permit_wait(waited);
+        Some(permit)
+    }
+
+    /// Send `request` to D1, holding a permit from the concurrency policy
+    /// while it is outstanding
+    ///
+    /// Every statement the connection sends goes through here. A span is
+    /// emitted for `sql`, with the rows `record_rows` reads off the result.
+    async fn send<T>(
+        &self,
+        operation: SpanOperation,
+        sql: Option<&str>,
+        request: impl Future<Output = QueryResult<T>>,
+        record_rows: impl FnOnce(&T, &mut D1Span),
+    ) -> QueryResult<T> {
+        let mut span = QuerySpan::start(
+            operation,
+            BackendType::Wasm,
+            sql,
+            self.span_emitter.as_ref(),
+        );
+        let permit = self.acquire_permit(&mut span).await;
+        let result = request.await;
+        if let Ok(value) = &result {
+            record_rows(value, span.span_mut());
+        }
+        if let Some(permit) = &permit {
+            permit.record_result(&result, None);
+        }
+        span.finish(&result);
+        result
+    }
+
+    /// Run a statement through `raw()`
+    async fn send_raw(&self, sql: &str, statement: &D1PreparedStatement) -> QueryResult<RawRows> {
+        self.send(
+            SpanOperation::Execute,
+            Some(sql),
+            run_raw_statement(statement),
+            |rows, span| span.rows_read = Some(rows.rows.len()),
+        )
+        .await
+    }
+
+    /// Send any writes the read policy requires, then run a read
+    async fn read_rows(
+        &mut self,
+        sql: &str,
+        statement: D1PreparedStatement,
+    ) -> QueryResult<Vec<D1Row>> {
+        // Queued writes are invisible to the read unless the policy sends
+        // them first
+        if self.transaction_manager.prepare_read()? {
+            let result = self.flush_transaction_queries().await;
+            self.transaction_manager.record_outcome(result)?;
+        }
+
+        Ok(self.send_raw(sql, &statement).await?.into_rows())
+    }
+
+    /// Execute several bound statements through a single `batch()` call
+    ///
+    /// D1 runs the statements as one SQL transaction, so a failure in any of
+    /// them rolls back the whole batch. `sql` is the text of the statements,
+    /// if known, for the span of the batch.
+    pub(crate) async fn execute_batch_statements(
+        &self,
+        sql: Option<&str>,
+        statements: Vec<D1PreparedStatement>,
+    ) -> QueryResult<Vec<D1Result>> {
+        self.limits.check_batch_size(statements.len())?;
+
+        let request = async {
+            let statements = statements.into_iter().collect::<Array>();
+            let promise = self.binding.batch(statements).map_err(|err| {
+                console_error!("{:?}", err);
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::Unknown,
+                    Box::new(D1Error {
+                        message: "Failed to execute batch".to_string(),
+                    }),
+                )
+            })?;
+
+            match SendableFuture(JsFuture::from(promise)).await {
+                Ok(results) => Ok(Array::from(&results).iter().map(D1Result::from).collect()),
+                Err(e) => {
+                    // The batch rejects with a JS `Error`, whose message may
+                    // name a failed guard
+                    Err(d1_database_error(js_error_message(&e)))
+                }
+            }
+        };
+
+        self.send(
+            SpanOperation::Batch,
+            sql,
+            request,
+            |results: &Vec<D1Result>, span| {
+                span.rows_written = Some(
+                    results
+                        .iter()
+                        .map(|result| statement_result(result).rows_affected)
+                        .sum(),
+                );
+            },
+        )
+        .await
+    }
+
+    /// Execute a [`BatchBuilder`] through a single `batch()` call
+    ///
+    /// D1 runs the statements as one SQL transaction, so if any of them fails
+    /// the whole batch is rolled back and an error is returned.
+    pub async fn execute_batch(&mut self, batch: BatchBuilder) -> QueryResult<BatchResult> {
+        if batch.is_empty() {
+            return Ok(BatchResult::from_statement_results(vec![]));
+        }
+
+        for statement in batch.statements() {
+            self.result_cache.invalidate(&statement.sql).await?;
+        }
+
+        let statements = batch.into_statements();
+        let sql = statements
+            .iter()
+            .map(|statement| statement.sql.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        let statements = statements
+            .iter()
+            .map(|statement| self.prepare_batch_statement(statement))
+            .collect::<QueryResult<Vec<_>>>()?;
+
+        let results = self
+            .execute_batch_statements(Some(&sql), statements)
+            .await?;
+
+        Ok(BatchResult::from_statement_results(
+            results.iter().map(statement_result).collect(),
+        ))
+    }
+
+    /// Insert many records, split into chunks that respect the connection's
+    /// bound parameter limit
+    ///
+    /// `build` turns a chunk of records into the insert statement. Every
+    /// chunk is sent in one batch, so the insert is all-or-nothing. Returns
+    /// the number of inserted rows.
+    ///
+    /// ```ignore
+    /// conn.chunked_insert(&new_users, |chunk| {
+    ///     diesel::insert_into(users::table).values(chunk)
+    /// })
+    /// .await?;
+    /// ```
+    pub async fn chunked_insert<'a, R, Q, F>(
+        &mut self,
+        records: &'a [R],
+        build: F,
+    ) -> QueryResult<usize>
+    where
+        Q: QueryFragment<D1Backend> + QueryId,
+        F: Fn(&'a [R]) -> Q,
+    {
+        let mut batch = BatchBuilder::new();
+        batch.push_chunked_insert_with_limit(records, self.limits.max_bound_parameters(), build)?;
+        Ok(self.execute_batch(batch).await?.total_rows_affected)
+    }
+
+    /// Load several queries at once
+    ///
+    /// Takes a tuple of queries and returns a tuple with one `Vec` of decoded
+    /// rows per query, in the same order.
+    ///
+    /// `batch()` returns rows as objects keyed by column name, which would
+    /// collapse columns that share a name, such as the `id` of both sides of
+    /// a join. The queries are instead run concurrently through `raw()`, like
+    /// [`load`](AsyncConnection::load), so rows keep every column in order.
+    /// Each query reads its own snapshot of the database.
+    ///
+    /// ```ignore
+    /// let (users, posts): (Vec<User>, Vec<Post>) = conn
+    ///     .batch_load((users::table.limit(10), posts::table.limit(10)))
+    ///     .await?;
+    /// ```
+    pub async fn batch_load<Q, Output>(&mut self, queries: Q) -> QueryResult<Output>
+    where
+        Q: BatchLoad<Output>,
+    {
+        // Queued writes are invisible to the reads unless the policy sends
+        // them first
+        if self.transaction_manager.prepare_read()? {
+            let result = self.flush_transaction_queries().await;
+            self.transaction_manager.record_outcome(result)?;
+        }
+
+        let statements = queries.into_statements()?;
+        let prepared = statements
+            .iter()
+            .map(|statement| self.prepare_batch_statement(statement))
+            .collect::<QueryResult<Vec<_>>>()?;
+
+        let results = try_join_all(
+            statements
+                .iter()
+                .zip(&prepared)
+                .map(|(statement, prepared)| self.send_raw(&statement.sql, prepared)),
+        )
+        .await?;
+
+        Q::decode_result_sets(results.into_iter().map(RawRows::into_rows).collect())
+    }
+
+    /// Load a query, serving its rows from the result cache for up to `ttl`
+    ///
+    /// Results are keyed by SQL plus binds. Writes issued through this
+    /// connection invalidate the cached results of the tables they touch;
+    /// writes made elsewhere become visible once the TTL expires. Results
+    /// read inside a transaction are not cached.
+    ///
+    /// ```ignore
+    /// let settings: Vec<Setting> = conn
+    ///     .load_cached(settings::table, Duration::from_secs(60))
+    ///     .await?;
+    /// ```
+    pub async fn load_cached<Q, U>(&mut self, query: Q, ttl: Duration) -> QueryResult<Vec<U>>
+    where
+        Q: AsQuery,
+        Q::Query: QueryFragment<D1Backend> + QueryId,
+        U: FromSqlRow<Q::SqlType, D1Backend>,
+    {
+        // Queued writes are invisible to the read unless the policy sends
+        // them first
+        if self.transaction_manager.prepare_read()? {
+            let result = self.flush_transaction_queries().await;
+            self.transaction_manager.record_outcome(result)?;
+        }
+
+        let statement = render_query(query.as_query())?;
+        let key = result_cache_key(&statement);
+        if let Some(rows) = self.result_cache.get(&key).await {
+            return decode_rows::<U, Q::SqlType, _>(Some(cached_rows_into_rows(rows)));
+        }
+
+        let prepared = self.prepare_batch_statement(&statement)?;
+        let rows = self.send_raw(&statement.sql, &prepared).await?;
+        if self.result_cache.enabled() && !self.transaction_manager.is_in_transaction() {
+            self.result_cache
+                .insert(key, &statement.sql, &rows.to_cached_rows(), ttl)
+                .await;
+        }
+
+        decode_rows::<U, Q::SqlType, _>(Some(rows.into_rows()))
+    }
+
+    /// Prepare and bind one statement of a [`BatchBuilder`]
+    fn prepare_batch_statement(
+        &self,
+        statement: &BatchStatement,
+    ) -> QueryResult<D1PreparedStatement> {
+        self.limits
+            .check_statement(&statement.sql, statement.param_count())?;
+
+        let binds = statement
+            .params
+            .iter()
+            .map(BoundValue::to_js_value)
+            .collect::<Array>();
+
+        self.statement_cache
+            .get_or_prepare(&statement.sql, &self.binding)
+            .and_then(|prepared| prepared.bind(binds))
+            .map_err(|err| {
+                console_error!("{:?}", err);
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::Unknown,
+                    Box::new(D1Error {
+                        message: "Failed to prepare statement".to_string(),
+                    }),
+                )
+            })
+    }
+
+    /// Send the buffered transaction statements as one batch and empty the queue
+    pub(crate) async fn flush_transaction_queries(&mut self) -> QueryResult<()> {
+        let statements = std::mem::take(&mut self.transaction_queries);
+        self.transaction_manager.reset_savepoints();
+        if !statements.is_empty() {
+            self.execute_batch_statements(None, statements).await?;
+        }
+        Ok(())
+    }
+}
+
+impl D1TransactionConnection for D1Connection {
+    fn d1_transaction_manager(&mut self) -> &mut D1TransactionManager {
+        &mut self.transaction_manager
+    }
+
+    fn transaction_queries(&mut self) -> &mut Vec<D1PreparedStatement> {
+        &mut self.transaction_queries
+    }
+
+    fn binding(&self) -> &D1Database {
+        &self.binding
+    }
 }
 
 // SAFETY: this is safe under WASM and workers because there's no threads and therefore no race conditions (at least memory ones)
@@ -82,18 +503,42 @@ unsafe impl Sync for D1Connection {}
 #[async_trait]
 impl SimpleAsyncConnection for D1Connection {
     async fn batch_execute(&mut self, query: &str) -> diesel::QueryResult<()> {
-        let statements = [JsValue::from_str(query)].iter().collect::<Array>();
+        self.limits.check_statement(query, 0)?;
+        self.result_cache.invalidate(query).await?;
+
+        // Inside a transaction the statement is buffered and sent on commit
+        if self.transaction_manager.is_in_transaction() {
+            let statement = self
+                .statement_cache
+                .get_or_prepare(query, &self.binding)
+                .map_err(|err| {
+                    console_error!("{:?}", err);
+                    diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::Unknown,
+                        Box::new(D1Error {
+                            message: "Failed to prepare statement".to_string(),
+                        }),
+                    )
+                })?;
+            self.transaction_queries.push(statement);
+            return Ok(());
+        }
 
-        match SendableFuture(JsFuture::from(self.binding.batch(statements).unwrap())).await {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                let error_msg = e.as_string().unwrap_or_else(|| "Unknown error".to_string());
-                Err(diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::Unknown,
-                    Box::new(D1Error { message: error_msg }),
-                ))
+        let request = async {
+            let statements = [JsValue::from_str(query)].iter().collect::<Array>();
+            match SendableFuture(JsFuture::from(self.binding.batch(statements).unwrap())).await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    let error_msg = e.as_string().unwrap_or_else(|| "Unknown error".to_string());
+                    Err(diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::Unknown,
+                        Box::new(D1Error { message: error_msg }),
+                    ))
+                }
             }
-        }
+        };
+        self.send(SpanOperation::Execute, Some(query), request, |_, _| {})
+            .await
     }
 }
 
@@ -118,62 +563,19 @@ impl AsyncConnection for D1Connection {
         T::Query: QueryFragment<Self::Backend> + QueryId + 'query,
     {
         let source = source.as_query();
-        let result = prepare_statement_sql(source, &self.binding);
+        let result = prepare_statement_sql(
+            source,
+            &self.binding,
+            &self.statement_cache,
+            &mut self.sql_cache,
+            &self.limits,
+        );
 
         SendableFuture(async move {
-            let promise = match result.all() {
-                Ok(res) => res,
-                Err(err) => {
-                    console_error!("{:?}", err);
-                    return Err(diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::Unknown,
-                        Box::new(D1Error {
-                            message: "Failed to execute query".to_string(),
-                        }),
-                    ));
-                }
-            };
-
-            let result = match SendableFuture(JsFuture::from(promise)).await {
-                Ok(res) => res,
-                Err(err) => {
-                    console_error!("{:?}", err);
-                    return Err(diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::Unknown,
-                        Box::new(D1Error {
-                            message: "Query execution failed".to_string(),
-                        }),
-                    ));
-                }
-            };
-
-            let result: D1Result = result.into();
-
-            let error = result.error().unwrap();
-
-            if let Some(error_str) = error {
-                return Err(diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::Unknown,
-                    Box::new(D1Error { message: error_str }),
-                ));
-            }
-
-            let array = result.results().unwrap().unwrap().to_vec();
-
-            if array.is_empty() {
-                return Ok(stream::iter(vec![]).boxed());
-            }
-
-            let field_keys: Vec<String> = js_sys::Object::keys(&Object::from(array[0].clone()))
-                .to_vec()
-                .iter()
-                .map(|val| val.as_string().unwrap())
-                .collect();
+            let (sql, statement) = result?;
 
-            let rows: Vec<QueryResult<D1Row>> = array
-                .iter()
-                .map(|val| Ok(D1Row::new(val.clone(), field_keys.clone())))
-                .collect();
+            let rows = self.read_rows(&sql, statement).await?;
+            let rows: Vec<QueryResult<D1Row>> = rows.into_iter().map(Ok).collect();
             let iter = stream::iter(rows).boxed();
             Ok(iter)
         })
@@ -187,52 +589,41 @@ impl AsyncConnection for D1Connection {
     where
         T: QueryFragment<Self::Backend> + QueryId + 'query,
     {
-        let result = prepare_statement_sql(source, &self.binding);
+        let result = prepare_statement_sql(
+            source,
+            &self.binding,
+            &self.statement_cache,
+            &mut self.sql_cache,
+            &self.limits,
+        );
         SendableFuture(async move {
-            let promise = match result.all() {
-                Ok(res) => res,
-                Err(err) => {
-                    console_error!("{:?}", err);
-                    return Err(diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::Unknown,
-                        Box::new(D1Error {
-                            message: "Failed to execute query".to_string(),
-                        }),
-                    ));
-                }
-            };
-
-            let result = match SendableFuture(JsFuture::from(promise)).await {
-                Ok(res) => res,
-                Err(err) => {
-                    console_error!("{:?}", err);
-                    return Err(diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::Unknown,
-                        Box::new(D1Error {
-                            message: "Query execution failed".to_string(),
-                        }),
-                    ));
-                }
-            };
-
-            let result: D1Result = result.into();
-
-            let error = result.error().unwrap();
-
-            if let Some(error_str) = error {
-                return Err(diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::Unknown,
-                    Box::new(D1Error { message: error_str }),
-                ));
+            let (sql, statement) = result?;
+            self.result_cache.invalidate(&sql).await?;
+
+            // Writes inside a transaction are buffered and sent as one atomic
+            // batch on commit, so the affected row count is not known yet
+            if self.transaction_manager.is_in_transaction() {
+                self.transaction_queries.push(statement);
+                return Ok(0);
             }
 
-            let meta = result.meta().unwrap();
-            let value = js_sys::Reflect::get(&meta, &"changes".to_owned().into())
-                .unwrap()
-                .as_f64()
-                .unwrap();
-
-            Ok(value as usize)
+            let request = async {
+                run_statement(&statement).await.map(|result| {
+                    let meta = result.meta().unwrap();
+                    let value = js_sys::Reflect::get(&meta, &"changes".to_owned().into())
+                        .unwrap()
+                        .as_f64()
+                        .unwrap();
+                    value as usize
+                })
+            };
+            self.send(
+                SpanOperation::Execute,
+                Some(&sql),
+                request,
+                |changes, span| span.rows_written = Some(*changes),
+            )
+            .await
         })
         .boxed()
     }
@@ -262,6 +653,121 @@ impl Instrumentation for NoopInstrumentation {
     }
 }
 
+/// Run a bound statement and check its result for an error
+async fn run_statement(statement: &D1PreparedStatement) -> QueryResult<D1Result> {
+    let promise = match statement.all() {
+        Ok(res) => res,
+        Err(err) => {
+            console_error!("{:?}", err);
+            return Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::Unknown,
+                Box::new(D1Error {
+                    message: "Failed to execute query".to_string(),
+                }),
+            ));
+        }
+    };
+
+    let result = match SendableFuture(JsFuture::from(promise)).await {
+        Ok(res) => res,
+        Err(err) => {
+            console_error!("{:?}", err);
+            return Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::Unknown,
+                Box::new(D1Error {
+                    message: "Query execution failed".to_string(),
+                }),
+            ));
+        }
+    };
+
+    let result: D1Result = result.into();
+
+    let error = result.error().unwrap();
+
+    if let Some(error_str) = error {
+        return Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new(D1Error { message: error_str }),
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Run a statement through `raw()`, which returns rows as arrays in the
+/// column order of the query
+async fn run_raw_statement(statement: &D1PreparedStatement) -> QueryResult<RawRows> {
+    let options = Object::new();
+    // Setting a property on a fresh object cannot fail
+    let _ = Reflect::set(&options, &"columnNames".into(), &JsValue::TRUE);
+
+    let promise = statement.raw_with_options(&options).map_err(|err| {
+        console_error!("{:?}", err);
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::Unknown,
+            Box::new(D1Error {
+                message: "Failed to execute query".to_string(),
+            }),
+        )
+    })?;
+
+    let result = SendableFuture(JsFuture::from(promise))
+        .await
+        .map_err(|err| d1_database_error(js_error_message(&err)))?;
+
+    // With `columnNames` set, the first array holds the column names
+    let array = Array::from(&result);
+    let mut arrays = array.iter().map(|row| Array::from(&row).to_vec());
+    let columns = arrays
+        .next()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|column| column.as_string())
+        .collect();
+
+    Ok(RawRows {
+        columns,
+        rows: arrays.collect(),
+    })
+}
+
+/// Rows of a statement run through `raw()`
+struct RawRows {
+    /// Column names, in the order of the query
+    columns: Vec<String>,
+    /// Values of each row, in column order
+    rows: Vec<Vec<JsValue>>,
+}
+
+impl RawRows {
+    /// Convert into rows, keeping columns that share a name
+    fn into_rows(self) -> Vec<D1Row> {
+        let RawRows { columns, rows } = self;
+        rows.into_iter()
+            .map(|values| D1Row::from_values(values, columns.clone()))
+            .collect()
+    }
+
+    /// Convert into the cacheable form of the result
+    fn to_cached_rows(&self) -> CachedRows {
+        let mut rows = CachedRows::new(self.columns.clone());
+        for values in &self.rows {
+            rows.push_row(values.iter().map(js_to_bound_value).collect());
+        }
+        rows
+    }
+}
+
+/// Get the message of a rejected D1 promise
+fn js_error_message(error: &JsValue) -> String {
+    error
+        .dyn_ref::<js_sys::Error>()
+        .map(|err| String::from(err.message()))
+        .or_else(|| error.as_string())
+        .unwrap_or_else(|| "Unknown error".to_string())
+}
+
 fn construct_bind_data<T>(query: &T) -> Result<Array, diesel::result::Error>
 where
     T: QueryFragment<D1Backend>,
@@ -278,29 +784,96 @@ where
     Ok(array)
 }
 
-fn prepare_statement_sql<'conn, 'query, T>(source: T, binding: &D1Database) -> D1PreparedStatement
+/// Convert a returned JS value into its SQLite storage class
+fn js_to_bound_value(value: &JsValue) -> BoundValue {
+    if value.is_null() || value.is_undefined() {
+        BoundValue::Null
+    } else if let Some(number) = value.as_f64() {
+        BoundValue::Float(number)
+    } else if let Some(text) = value.as_string() {
+        BoundValue::Text(text)
+    } else if let Some(b) = value.as_bool() {
+        BoundValue::Integer(i64::from(b))
+    } else if value.is_instance_of::<js_sys::Uint8Array>() {
+        BoundValue::Binary(js_sys::Uint8Array::from(value.clone()).to_vec())
+    } else {
+        js_sys::JSON::stringify(value)
+            .ok()
+            .and_then(|s| s.as_string())
+            .map_or(BoundValue::Null, BoundValue::Text)
+    }
+}
+
+/// Rebuild rows from a cached result
+fn cached_rows_into_rows(rows: CachedRows) -> Vec<D1Row> {
+    let CachedRows { columns, rows } = rows;
+    rows.into_iter()
+        .map(|values| {
+            D1Row::from_values(
+                values.iter().map(BoundValue::to_js_value).collect(),
+                columns.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Convert one result of a `batch()` call into its per-statement result
+fn statement_result(result: &D1Result) -> StatementResult {
+    let meta = result.meta().ok();
+    let meta_number = |key: &str| {
+        meta.as_ref()
+            .and_then(|meta| Reflect::get(meta, &key.into()).ok())
+            .and_then(|value| value.as_f64())
+    };
+
+    let statement_result = if result.success().unwrap_or(false) {
+        StatementResult::success(meta_number("changes").unwrap_or(0.0) as usize)
+    } else {
+        let error = result
+            .error()
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| "Unknown error".to_string());
+        StatementResult::failure(error)
+    };
+
+    if meta.is_none() {
+        return statement_result;
+    }
+    statement_result.with_meta(StatementMeta {
+        duration_ms: meta_number("duration"),
+        rows_read: meta_number("rows_read").map(|n| n as usize),
+        rows_written: meta_number("rows_written").map(|n| n as usize),
+        last_row_id: meta_number("last_row_id").map(|n| n as i64),
+    })
+}
+
+/// Render, check and bind a query, returning its SQL with the bound statement
+fn prepare_statement_sql<'conn, 'query, T>(
+    source: T,
+    binding: &D1Database,
+    statement_cache: &PreparedStatementCache,
+    sql_cache: &mut SqlCache,
+    limits: &D1Limits,
+) -> QueryResult<(String, D1PreparedStatement)>
 where
     T: QueryFragment<D1Backend> + QueryId + 'query,
 {
-    let mut query_builder = D1QueryBuilder::default();
-    source.to_sql(&mut query_builder, &D1Backend).unwrap();
-    let result = match binding.prepare(&query_builder.sql) {
-        Ok(res) => res,
-        Err(err) => {
-            console_error!("{:?}", err);
-            panic!("Failed to prepare statement");
-        }
-    };
+    // Static queries reuse their rendered SQL, only the binds are collected
+    let sql = sql_cache.sql(&source)?;
+    let binds = construct_bind_data(&source)?;
+    let param_count = binds.length() as usize;
 
-    let binds = construct_bind_data(&source).unwrap();
+    // Reject statements D1 would refuse before they reach the binding
+    limits.check_statement(&sql, param_count)?;
 
-    match result.bind(binds) {
-        Ok(res) => res,
-        Err(err) => {
-            console_error!("{:?}", err);
-            panic!("Failed to bind parameters");
-        }
-    }
+    // A cached handle skips `prepare`, only the binds are applied
+    let statement = statement_cache
+        .get_or_prepare(&sql, binding)
+        .and_then(|prepared| prepared.bind(binds))
+        .map_err(|err| d1_database_error(js_error_message(&err)))?;
+
+    Ok((sql, statement))
 }
 
 #[cfg(test)]